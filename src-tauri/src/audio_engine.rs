@@ -0,0 +1,144 @@
+use cpal::traits::{DeviceTrait, HostTrait};
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use rodio::source::Zero;
+use rodio::{OutputStream, Source};
+use std::sync::{mpsc, Arc};
+
+use crate::errors::SoundsError;
+use crate::master_bus::{BusControls, ListenerOutput, UserOutput};
+
+/// A long-lived output that any number of voices can be mixed into.
+struct Bus {
+    mixer: Arc<DynamicMixerController<f32>>,
+}
+
+impl Bus {
+    /// `output` wraps the mix in whatever processing the bus applies before the device.
+    fn open<F, S>(device: &cpal::Device, output: F) -> Result<(OutputStream, Self), SoundsError>
+    where
+        F: FnOnce(DynamicMixer<f32>) -> S,
+        S: Source<Item = f32> + Send + 'static,
+    {
+        let config = device
+            .default_output_config()
+            .map_err(|_| SoundsError::OpenOutputStream)?;
+        let (stream, stream_handle) =
+            OutputStream::try_from_device(device).map_err(|_| SoundsError::OpenOutputStream)?;
+
+        let (mixer, mixer_output) =
+            dynamic_mixer::mixer::<f32>(config.channels(), config.sample_rate().0);
+        // The mixer ends as soon as it runs out of sources, so keep a silent one in it forever
+        mixer.add(Zero::<f32>::new(config.channels(), config.sample_rate().0));
+        stream_handle
+            .play_raw(output(mixer_output))
+            .map_err(|_| SoundsError::OpenOutputStream)?;
+
+        Ok((stream, Bus { mixer }))
+    }
+
+    fn add<S>(&self, source: S)
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        self.mixer.add(source);
+    }
+}
+
+/// Owns one output stream per configured device for the lifetime of the app, so playing
+/// a sound only has to hand a voice to each bus instead of opening devices on every press.
+pub struct AudioEngine {
+    /// The virtual audio device that other people in the call hear
+    listener_bus: Bus,
+    /// The user's own output device, so they hear what they play
+    user_bus: Bus,
+    // Dropping this sender tells the stream thread to close its streams
+    _shutdown: mpsc::Sender<()>,
+}
+
+impl AudioEngine {
+    pub fn start(
+        in_device: String,
+        out_device: String,
+        controls: Arc<BusControls>,
+    ) -> Result<Self, SoundsError> {
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let (shutdown_tx, shutdown_rx) = mpsc::channel::<()>();
+
+        // cpal streams can't be sent across threads, so they live on a thread of their own
+        // that only wakes up once the engine is dropped
+        std::thread::spawn(
+            move || match open_buses(&in_device, &out_device, controls) {
+                Ok((streams, buses)) => {
+                    let _ = ready_tx.send(Ok(buses));
+
+                    // Blocks until the engine drops its end of the channel
+                    let _ = shutdown_rx.recv();
+                    drop(streams);
+                    println!("Closed audio engine streams");
+                }
+                Err(error) => {
+                    let _ = ready_tx.send(Err(error));
+                }
+            },
+        );
+
+        let (listener_bus, user_bus) = ready_rx
+            .recv()
+            .map_err(|_| SoundsError::OpenOutputStream)??;
+
+        Ok(AudioEngine {
+            listener_bus,
+            user_bus,
+            _shutdown: shutdown_tx,
+        })
+    }
+
+    /// Queues a voice on each bus; both start on the next audio callback.
+    pub fn play<L, U>(&self, listener_voice: L, user_voice: U)
+    where
+        L: Source<Item = f32> + Send + 'static,
+        U: Source<Item = f32> + Send + 'static,
+    {
+        self.listener_bus.add(listener_voice);
+        self.user_bus.add(user_voice);
+    }
+
+    /// Queues a voice that only the listener bus plays.
+    pub fn play_listener<L>(&self, listener_voice: L)
+    where
+        L: Source<Item = f32> + Send + 'static,
+    {
+        self.listener_bus.add(listener_voice);
+    }
+}
+
+fn find_output_device(host: &cpal::Host, name: &str) -> Option<cpal::Device> {
+    host.output_devices()
+        .ok()?
+        .find(|device| device.name().unwrap_or("".to_owned()).contains(name))
+}
+
+/// Opens the listener and user buses. The returned streams have to be kept alive for as
+/// long as the buses are used.
+fn open_buses(
+    in_device: &str,
+    out_device: &str,
+    controls: Arc<BusControls>,
+) -> Result<([OutputStream; 2], (Bus, Bus)), SoundsError> {
+    let host = cpal::default_host();
+
+    let listener_device =
+        find_output_device(&host, in_device).ok_or(SoundsError::FindOutputDevice)?;
+    // Fall back to the default device when the configured one is unplugged
+    let user_device = find_output_device(&host, out_device)
+        .or_else(|| host.default_output_device())
+        .ok_or(SoundsError::FindOutputDevice)?;
+
+    let (listener_stream, listener_bus) = Bus::open(&listener_device, |mixer| {
+        ListenerOutput::new(mixer, Arc::clone(&controls))
+    })?;
+    let (user_stream, user_bus) =
+        Bus::open(&user_device, |mixer| UserOutput::new(mixer, controls))?;
+
+    Ok(([listener_stream, user_stream], (listener_bus, user_bus)))
+}
@@ -0,0 +1,252 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{BoxedSource, Effect};
+
+/// Each stage is skipped when it's `None`. They run in the order they're declared.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DistortionSettings {
+    /// Gain pushed into a soft clipper, 1.0 is barely audible
+    #[serde(default)]
+    pub overdrive: Option<f32>,
+    /// Level samples are cut off at, the clipped sound is then brought back up to full scale
+    #[serde(default)]
+    pub hard_clip: Option<f32>,
+    #[serde(default)]
+    pub bitcrush: Option<BitcrushSettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BitcrushSettings {
+    /// Bits left to describe each sample
+    pub bit_depth: u8,
+    /// Every sample is held for this many frames, dividing the sample rate
+    pub downsample: u16,
+}
+
+impl DistortionSettings {
+    pub const MAX_OVERDRIVE: f32 = 100.0;
+    pub const MIN_HARD_CLIP: f32 = 0.01;
+    pub const MAX_BIT_DEPTH: u8 = 16;
+    pub const MAX_DOWNSAMPLE: u16 = 64;
+}
+
+impl Effect for DistortionSettings {
+    fn is_valid(&self) -> bool {
+        self.overdrive
+            .is_none_or(|drive| (1.0..=Self::MAX_OVERDRIVE).contains(&drive))
+            && self
+                .hard_clip
+                .is_none_or(|level| (Self::MIN_HARD_CLIP..=1.0).contains(&level))
+            && self.bitcrush.as_ref().is_none_or(|bitcrush| {
+                (1..=Self::MAX_BIT_DEPTH).contains(&bitcrush.bit_depth)
+                    && (1..=Self::MAX_DOWNSAMPLE).contains(&bitcrush.downsample)
+            })
+    }
+
+    /// Runs the source through each enabled stage.
+    fn apply(&self, mut source: BoxedSource) -> BoxedSource {
+        if let Some(drive) = self.overdrive {
+            source = Box::new(SoftClip::new(source, drive));
+        }
+        if let Some(level) = self.hard_clip {
+            source = Box::new(HardClip::new(source, level));
+        }
+        if let Some(bitcrush) = &self.bitcrush {
+            source = Box::new(Bitcrush::new(source, bitcrush));
+        }
+
+        source
+    }
+}
+
+/// Overdrive that rounds off peaks with `tanh`, so it never goes past full scale.
+pub struct SoftClip<S> {
+    source: S,
+    drive: f32,
+}
+
+impl<S> SoftClip<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, drive: f32) -> Self {
+        SoftClip {
+            source,
+            drive: drive.clamp(1.0, DistortionSettings::MAX_OVERDRIVE),
+        }
+    }
+}
+
+impl<S> Iterator for SoftClip<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        self.source
+            .next()
+            .map(|sample| (sample * self.drive).tanh())
+    }
+}
+
+impl<S> Source for SoftClip<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Flattens everything above `level`, then makes up the lost gain.
+pub struct HardClip<S> {
+    source: S,
+    level: f32,
+}
+
+impl<S> HardClip<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, level: f32) -> Self {
+        HardClip {
+            source,
+            level: level.clamp(DistortionSettings::MIN_HARD_CLIP, 1.0),
+        }
+    }
+}
+
+impl<S> Iterator for HardClip<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        self.source
+            .next()
+            .map(|sample| sample.clamp(-self.level, self.level) / self.level)
+    }
+}
+
+impl<S> Source for HardClip<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Lowers the bit depth and sample rate while keeping the stream's own format.
+pub struct Bitcrush<S> {
+    source: S,
+    /// Quantization steps on each side of zero
+    steps: f32,
+    downsample: u16,
+    /// The last frame that was sampled, one value per channel
+    held: Vec<f32>,
+    channel: usize,
+    frames_held: u16,
+}
+
+impl<S> Bitcrush<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, settings: &BitcrushSettings) -> Self {
+        let bit_depth = settings
+            .bit_depth
+            .clamp(1, DistortionSettings::MAX_BIT_DEPTH);
+        let channels = source.channels() as usize;
+
+        Bitcrush {
+            source,
+            steps: (1u32 << (bit_depth - 1)) as f32,
+            downsample: settings
+                .downsample
+                .clamp(1, DistortionSettings::MAX_DOWNSAMPLE),
+            held: vec![0.0; channels],
+            channel: 0,
+            frames_held: 0,
+        }
+    }
+}
+
+impl<S> Iterator for Bitcrush<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next()?;
+
+        if self.frames_held == 0 {
+            self.held[self.channel] = (sample * self.steps).round() / self.steps;
+        }
+        let crushed = self.held[self.channel];
+
+        // Samples are interleaved, so a frame is only done once every channel has been read
+        self.channel += 1;
+        if self.channel == self.held.len() {
+            self.channel = 0;
+            self.frames_held = (self.frames_held + 1) % self.downsample;
+        }
+
+        Some(crushed)
+    }
+}
+
+impl<S> Source for Bitcrush<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -0,0 +1,115 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{BoxedSource, Effect, Tail};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EchoSettings {
+    /// Time between repeats
+    pub delay_ms: u32,
+    /// How much of each repeat is fed back into the next one, 0.0 to 0.95
+    pub feedback: f32,
+    /// Mix between the dry sound (0.0) and the echoes alone (1.0)
+    pub wet: f32,
+}
+
+impl EchoSettings {
+    pub const MAX_DELAY_MS: u32 = 5_000;
+    pub const MAX_FEEDBACK: f32 = 0.95;
+}
+
+impl Effect for EchoSettings {
+    fn is_valid(&self) -> bool {
+        (1..=Self::MAX_DELAY_MS).contains(&self.delay_ms)
+            && (0.0..=Self::MAX_FEEDBACK).contains(&self.feedback)
+            && (0.0..=1.0).contains(&self.wet)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(Echo::new(source, self))
+    }
+}
+
+/// A feedback delay line. Keeps playing after the sound ends until the echoes die out.
+pub struct Echo<S> {
+    source: S,
+    channels: u16,
+    sample_rate: u32,
+    buffer: Vec<f32>,
+    position: usize,
+    feedback: f32,
+    wet: f32,
+    tail: Tail,
+}
+
+impl<S> Echo<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, settings: &EchoSettings) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let feedback = settings.feedback.clamp(0.0, EchoSettings::MAX_FEEDBACK);
+
+        let delay_ms = settings.delay_ms.clamp(1, EchoSettings::MAX_DELAY_MS);
+        let delay_frames = (sample_rate as u64 * delay_ms as u64 / 1000).max(1) as usize;
+        // Interleaved samples, so each channel is delayed by exactly one period
+        let delay_len = delay_frames * channels as usize;
+
+        let repeats = Tail::repeats(feedback);
+
+        Echo {
+            source,
+            channels,
+            sample_rate,
+            buffer: vec![0.0; delay_len],
+            position: 0,
+            feedback,
+            wet: settings.wet.clamp(0.0, 1.0),
+            tail: Tail::new(delay_len * (repeats + 1)),
+        }
+    }
+}
+
+impl<S> Iterator for Echo<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let dry = self.tail.next(&mut self.source)?;
+
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = dry + delayed * self.feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        Some(dry * (1.0 - self.wet) + delayed * self.wet)
+    }
+}
+
+impl<S> Source for Echo<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // The tail keeps going after the source's last frame
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let tail = self.tail.duration(self.sample_rate, self.channels);
+        self.source.total_duration().map(|duration| duration + tail)
+    }
+}
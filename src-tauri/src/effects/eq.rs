@@ -0,0 +1,340 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{FRAC_1_SQRT_2, PI};
+use std::time::Duration;
+
+use super::{BoxedSource, Effect};
+
+const MIN_FREQUENCY: f32 = 20.0;
+const MAX_FREQUENCY: f32 = 20_000.0;
+const MAX_GAIN_DB: f32 = 24.0;
+const MIN_Q: f32 = 0.1;
+const MAX_Q: f32 = 10.0;
+
+fn is_valid_frequency(frequency: f32) -> bool {
+    (MIN_FREQUENCY..=MAX_FREQUENCY).contains(&frequency)
+}
+
+fn is_valid_gain(gain_db: f32) -> bool {
+    (-MAX_GAIN_DB..=MAX_GAIN_DB).contains(&gain_db)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum EqBand {
+    LowShelf {
+        frequency: f32,
+        gain_db: f32,
+    },
+    Peaking {
+        frequency: f32,
+        gain_db: f32,
+        q: f32,
+    },
+    HighShelf {
+        frequency: f32,
+        gain_db: f32,
+    },
+}
+
+impl EqBand {
+    fn is_valid(&self) -> bool {
+        match *self {
+            EqBand::LowShelf { frequency, gain_db } | EqBand::HighShelf { frequency, gain_db } => {
+                is_valid_frequency(frequency) && is_valid_gain(gain_db)
+            }
+            EqBand::Peaking {
+                frequency,
+                gain_db,
+                q,
+            } => {
+                is_valid_frequency(frequency)
+                    && is_valid_gain(gain_db)
+                    && (MIN_Q..=MAX_Q).contains(&q)
+            }
+        }
+    }
+
+    fn biquad(&self, sample_rate: u32) -> Biquad {
+        match *self {
+            EqBand::LowShelf { frequency, gain_db } => {
+                Biquad::low_shelf(sample_rate, frequency, gain_db)
+            }
+            EqBand::Peaking {
+                frequency,
+                gain_db,
+                q,
+            } => Biquad::peaking(sample_rate, frequency, gain_db, q),
+            EqBand::HighShelf { frequency, gain_db } => {
+                Biquad::high_shelf(sample_rate, frequency, gain_db)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct EqSettings {
+    pub bands: Vec<EqBand>,
+}
+
+impl EqSettings {
+    pub const MAX_BANDS: usize = 16;
+}
+
+impl Effect for EqSettings {
+    fn is_valid(&self) -> bool {
+        self.bands.len() <= Self::MAX_BANDS && self.bands.iter().all(EqBand::is_valid)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        let sample_rate = source.sample_rate();
+        let biquads = self
+            .bands
+            .iter()
+            .map(|band| band.biquad(sample_rate))
+            .collect();
+
+        Box::new(Equalizer::new(source, biquads))
+    }
+}
+
+/// Cuts off everything below `high_pass` and above `low_pass`, each only when set.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilterSettings {
+    #[serde(default)]
+    pub high_pass: Option<f32>,
+    #[serde(default)]
+    pub low_pass: Option<f32>,
+}
+
+impl Effect for FilterSettings {
+    fn is_valid(&self) -> bool {
+        self.high_pass.is_none_or(is_valid_frequency)
+            && self.low_pass.is_none_or(is_valid_frequency)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        let sample_rate = source.sample_rate();
+        let biquads = self
+            .high_pass
+            .map(|frequency| Biquad::high_pass(sample_rate, frequency))
+            .into_iter()
+            .chain(
+                self.low_pass
+                    .map(|frequency| Biquad::low_pass(sample_rate, frequency)),
+            )
+            .collect();
+
+        Box::new(Equalizer::new(source, biquads))
+    }
+}
+
+/// Thins out bass and brings voices forward, so sounds survive the call's voice codec.
+pub fn voice_chat_preset(sample_rate: u32) -> Vec<Biquad> {
+    vec![
+        Biquad::high_pass(sample_rate, 100.0),
+        Biquad::low_shelf(sample_rate, 250.0, -4.0),
+        Biquad::peaking(sample_rate, 3_000.0, 3.0, 1.0),
+        Biquad::low_pass(sample_rate, 12_000.0),
+    ]
+}
+
+/// Coefficients of a second order filter, from the Audio EQ Cookbook. Normalized so `a0`
+/// is 1.
+#[derive(Debug, Clone, Copy)]
+pub struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Biquad {
+    /// Keeps the frequency under Nyquist, where the filter would become unstable
+    fn omega(sample_rate: u32, frequency: f32) -> (f32, f32) {
+        let frequency = frequency.clamp(1.0, sample_rate as f32 * 0.45);
+        let omega = 2.0 * PI * frequency / sample_rate as f32;
+        (omega.cos(), omega.sin())
+    }
+
+    pub fn normalized(b0: f32, b1: f32, b2: f32, a0: f32, a1: f32, a2: f32) -> Self {
+        Biquad {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+
+    pub fn low_pass(sample_rate: u32, frequency: f32) -> Self {
+        let (cos, sin) = Self::omega(sample_rate, frequency);
+        let alpha = sin / (2.0 * FRAC_1_SQRT_2);
+
+        Self::normalized(
+            (1.0 - cos) / 2.0,
+            1.0 - cos,
+            (1.0 - cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn high_pass(sample_rate: u32, frequency: f32) -> Self {
+        let (cos, sin) = Self::omega(sample_rate, frequency);
+        let alpha = sin / (2.0 * FRAC_1_SQRT_2);
+
+        Self::normalized(
+            (1.0 + cos) / 2.0,
+            -(1.0 + cos),
+            (1.0 + cos) / 2.0,
+            1.0 + alpha,
+            -2.0 * cos,
+            1.0 - alpha,
+        )
+    }
+
+    pub fn peaking(sample_rate: u32, frequency: f32, gain_db: f32, q: f32) -> Self {
+        let (cos, sin) = Self::omega(sample_rate, frequency);
+        let a = 10f32.powf(gain_db / 40.0);
+        let alpha = sin / (2.0 * q.clamp(MIN_Q, MAX_Q));
+
+        Self::normalized(
+            1.0 + alpha * a,
+            -2.0 * cos,
+            1.0 - alpha * a,
+            1.0 + alpha / a,
+            -2.0 * cos,
+            1.0 - alpha / a,
+        )
+    }
+
+    pub fn low_shelf(sample_rate: u32, frequency: f32, gain_db: f32) -> Self {
+        let (cos, sin) = Self::omega(sample_rate, frequency);
+        let a = 10f32.powf(gain_db / 40.0);
+        let shelf = 2.0 * a.sqrt() * sin / (2.0 * FRAC_1_SQRT_2);
+
+        Self::normalized(
+            a * ((a + 1.0) - (a - 1.0) * cos + shelf),
+            2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+            a * ((a + 1.0) - (a - 1.0) * cos - shelf),
+            (a + 1.0) + (a - 1.0) * cos + shelf,
+            -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+            (a + 1.0) + (a - 1.0) * cos - shelf,
+        )
+    }
+
+    pub fn high_shelf(sample_rate: u32, frequency: f32, gain_db: f32) -> Self {
+        let (cos, sin) = Self::omega(sample_rate, frequency);
+        let a = 10f32.powf(gain_db / 40.0);
+        let shelf = 2.0 * a.sqrt() * sin / (2.0 * FRAC_1_SQRT_2);
+
+        Self::normalized(
+            a * ((a + 1.0) + (a - 1.0) * cos + shelf),
+            -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+            a * ((a + 1.0) + (a - 1.0) * cos - shelf),
+            (a + 1.0) - (a - 1.0) * cos + shelf,
+            2.0 * ((a - 1.0) - (a + 1.0) * cos),
+            (a + 1.0) - (a - 1.0) * cos - shelf,
+        )
+    }
+}
+
+/// Runs interleaved samples through a series of biquads, keeping separate state per channel.
+pub struct FilterBank {
+    biquads: Vec<Biquad>,
+    /// `[x1, x2, y1, y2]` for every biquad of every channel
+    state: Vec<[f32; 4]>,
+    channels: usize,
+    channel: usize,
+}
+
+impl FilterBank {
+    pub fn new(biquads: Vec<Biquad>, channels: u16) -> Self {
+        let channels = channels.max(1) as usize;
+
+        FilterBank {
+            state: vec![[0.0; 4]; biquads.len() * channels],
+            biquads,
+            channels,
+            channel: 0,
+        }
+    }
+
+    /// Filters the next sample, which belongs to the channel after the previous one.
+    #[inline]
+    pub fn process(&mut self, mut sample: f32) -> f32 {
+        let states = &mut self.state[self.channel * self.biquads.len()..];
+
+        for (biquad, state) in self.biquads.iter().zip(states.iter_mut()) {
+            let [x1, x2, y1, y2] = *state;
+            let output = biquad.b0 * sample + biquad.b1 * x1 + biquad.b2 * x2
+                - biquad.a1 * y1
+                - biquad.a2 * y2;
+            *state = [sample, x1, output, y1];
+            sample = output;
+        }
+
+        self.channel = (self.channel + 1) % self.channels;
+        sample
+    }
+}
+
+pub struct Equalizer<S> {
+    source: S,
+    filters: FilterBank,
+}
+
+impl<S> Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, biquads: Vec<Biquad>) -> Self {
+        let filters = FilterBank::new(biquads, source.channels());
+
+        Equalizer { source, filters }
+    }
+}
+
+impl<S> Iterator for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        self.source
+            .next()
+            .map(|sample| self.filters.process(sample))
+    }
+}
+
+impl<S> Source for Equalizer<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -0,0 +1,27 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use super::{BoxedSource, Effect};
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GainSettings {
+    /// Boost or cut in decibels, applied wherever the gain sits in the chain
+    pub db: f32,
+}
+
+impl GainSettings {
+    pub const MIN_DB: f32 = -60.0;
+    pub const MAX_DB: f32 = 24.0;
+}
+
+impl Effect for GainSettings {
+    fn is_valid(&self) -> bool {
+        (Self::MIN_DB..=Self::MAX_DB).contains(&self.db)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        let db = self.db.clamp(Self::MIN_DB, Self::MAX_DB);
+        Box::new(source.amplify(10f32.powf(db / 20.0)))
+    }
+}
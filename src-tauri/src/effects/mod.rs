@@ -0,0 +1,190 @@
+//! `Source` adapters that process a decoded sound before it reaches the audio engine.
+
+use rodio::Source;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::time::Duration;
+
+use self::distortion::DistortionSettings;
+use self::echo::EchoSettings;
+use self::eq::{EqSettings, FilterSettings};
+use self::gain::GainSettings;
+use self::pitch::PitchSettings;
+use self::reverb::ReverbSettings;
+
+pub mod distortion;
+pub mod echo;
+pub mod eq;
+pub mod gain;
+pub mod pitch;
+pub mod reverb;
+
+/// A sound on its way through an effect chain
+pub type BoxedSource = Box<dyn Source<Item = f32> + Send>;
+
+/// How quiet a feedback effect has to get before its tail is cut off (-60 dB)
+const TAIL_THRESHOLD: f32 = 0.001;
+
+/// Silence fed through a feedback effect once its source has ended, so it can ring out.
+pub struct Tail {
+    /// Samples left to play once the source has ended
+    left: Option<usize>,
+    len: usize,
+}
+
+impl Tail {
+    /// A tail of `len` interleaved samples
+    pub fn new(len: usize) -> Self {
+        Tail { left: None, len }
+    }
+
+    /// Trips around a feedback loop it takes to fall under the threshold
+    pub fn repeats(feedback: f32) -> usize {
+        if feedback > 0.0 {
+            (TAIL_THRESHOLD.ln() / feedback.ln()).ceil() as usize
+        } else {
+            0
+        }
+    }
+
+    /// The next input sample for the effect: the source's, then silence until the tail is
+    /// over.
+    pub fn next<S>(&mut self, source: &mut S) -> Option<f32>
+    where
+        S: Iterator<Item = f32>,
+    {
+        let left = match self.left.as_mut() {
+            Some(left) => left,
+            None => match source.next() {
+                Some(sample) => return Some(sample),
+                None => self.left.insert(self.len),
+            },
+        };
+
+        if *left == 0 {
+            return None;
+        }
+        *left -= 1;
+
+        Some(0.0)
+    }
+
+    pub fn duration(&self, sample_rate: u32, channels: u16) -> Duration {
+        let frames = (self.len / channels.max(1) as usize) as u64;
+        Duration::from_micros(frames * 1_000_000 / sample_rate as u64)
+    }
+}
+
+/// The settings of one kind of effect. Adding an effect means implementing this and giving
+/// it a variant in `EffectConfig`.
+pub trait Effect {
+    /// Checked before the settings are saved, so the UI can't store values the effect
+    /// can't handle
+    fn is_valid(&self) -> bool;
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource;
+
+    /// How long a sound of `duration` plays for once it goes through this effect
+    fn duration(&self, duration: Duration) -> Duration {
+        duration
+    }
+}
+
+/// One entry of a sound's effect chain, stored in settings.json as an object with a `kind`.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum EffectConfig {
+    Gain(GainSettings),
+    Echo(EchoSettings),
+    Distortion(DistortionSettings),
+    Pitch(PitchSettings),
+    Eq(EqSettings),
+    Filter(FilterSettings),
+    Reverb(ReverbSettings),
+    /// An effect this version can't read, e.g. one added by a newer version. Kept as is so
+    /// saving the settings again doesn't lose it.
+    #[serde(untagged)]
+    Unknown(serde_json::Value),
+}
+
+/// `EffectConfig` as it's read, where only the `kind` decides whether an effect is unknown.
+/// A known effect with missing or wrong fields fails to read.
+#[derive(Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+enum KnownEffectConfig {
+    Gain(GainSettings),
+    Echo(EchoSettings),
+    Distortion(DistortionSettings),
+    Pitch(PitchSettings),
+    Eq(EqSettings),
+    Filter(FilterSettings),
+    Reverb(ReverbSettings),
+    #[serde(other)]
+    Unknown,
+}
+
+impl<'de> Deserialize<'de> for EffectConfig {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+
+        Ok(match KnownEffectConfig::deserialize(&value) {
+            Ok(KnownEffectConfig::Gain(settings)) => EffectConfig::Gain(settings),
+            Ok(KnownEffectConfig::Echo(settings)) => EffectConfig::Echo(settings),
+            Ok(KnownEffectConfig::Distortion(settings)) => EffectConfig::Distortion(settings),
+            Ok(KnownEffectConfig::Pitch(settings)) => EffectConfig::Pitch(settings),
+            Ok(KnownEffectConfig::Eq(settings)) => EffectConfig::Eq(settings),
+            Ok(KnownEffectConfig::Filter(settings)) => EffectConfig::Filter(settings),
+            Ok(KnownEffectConfig::Reverb(settings)) => EffectConfig::Reverb(settings),
+            Ok(KnownEffectConfig::Unknown) => EffectConfig::Unknown(value),
+            // Kept and skipped like an unknown effect rather than failing the whole settings file
+            Err(error) => {
+                eprintln!("Ignoring effect from settings file: {}", error);
+                EffectConfig::Unknown(value)
+            }
+        })
+    }
+}
+
+impl EffectConfig {
+    /// Whether this is an effect of a known kind that couldn't be read, rather than one this
+    /// version doesn't know. These are only accepted from the settings file.
+    pub fn is_malformed(&self) -> bool {
+        match self {
+            EffectConfig::Unknown(value) => KnownEffectConfig::deserialize(value).is_err(),
+            _ => false,
+        }
+    }
+
+    /// `None` for unknown effects, which are skipped during playback
+    pub fn effect(&self) -> Option<&dyn Effect> {
+        match self {
+            EffectConfig::Gain(settings) => Some(settings),
+            EffectConfig::Echo(settings) => Some(settings),
+            EffectConfig::Distortion(settings) => Some(settings),
+            EffectConfig::Pitch(settings) => Some(settings),
+            EffectConfig::Eq(settings) => Some(settings),
+            EffectConfig::Filter(settings) => Some(settings),
+            EffectConfig::Reverb(settings) => Some(settings),
+            EffectConfig::Unknown(_) => None,
+        }
+    }
+}
+
+/// Runs `source` through every known effect in `effects`, in order.
+pub fn apply_chain(mut source: BoxedSource, effects: &[EffectConfig]) -> BoxedSource {
+    for effect in effects.iter().filter_map(EffectConfig::effect) {
+        source = effect.apply(source);
+    }
+
+    source
+}
+
+/// How long a sound of `duration` plays for once it goes through `effects`.
+pub fn chain_duration(duration: Duration, effects: &[EffectConfig]) -> Duration {
+    effects
+        .iter()
+        .filter_map(EffectConfig::effect)
+        .fold(duration, |duration, effect| effect.duration(duration))
+}
@@ -0,0 +1,245 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::time::Duration;
+
+use super::{BoxedSource, Effect};
+
+/// Length of the grains the sound is cut into, long enough to hold a few periods of low
+/// voices without smearing transients too much
+const GRAIN_MS: u32 = 40;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PitchSettings {
+    /// Shift in semitones, without changing the speed
+    pub semitones: f32,
+    /// Playback speed, without changing the pitch. 2.0 plays twice as fast
+    pub speed: f32,
+}
+
+impl PitchSettings {
+    pub const MAX_SEMITONES: f32 = 24.0;
+    pub const MIN_SPEED: f32 = 0.25;
+    pub const MAX_SPEED: f32 = 4.0;
+
+    fn pitch_ratio(&self) -> f32 {
+        let semitones = self
+            .semitones
+            .clamp(-Self::MAX_SEMITONES, Self::MAX_SEMITONES);
+        2f32.powf(semitones / 12.0)
+    }
+
+    fn speed(&self) -> f32 {
+        self.speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED)
+    }
+}
+
+impl Effect for PitchSettings {
+    fn is_valid(&self) -> bool {
+        (-Self::MAX_SEMITONES..=Self::MAX_SEMITONES).contains(&self.semitones)
+            && (Self::MIN_SPEED..=Self::MAX_SPEED).contains(&self.speed)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        if self.pitch_ratio() == 1.0 && self.speed() == 1.0 {
+            return source;
+        }
+
+        Box::new(PitchShift::new(source, self))
+    }
+
+    fn duration(&self, duration: Duration) -> Duration {
+        duration.div_f32(self.speed())
+    }
+}
+
+/// Granular time-stretch. Overlapping windowed grains are read from the source at the pitch
+/// ratio, while the grains themselves move through the source at the playback speed, so
+/// the two can be changed independently.
+pub struct PitchShift<S> {
+    source: S,
+    channels: usize,
+    sample_rate: u32,
+    pitch: f32,
+    speed: f32,
+    /// Frames per grain, grains overlap by half
+    grain_len: usize,
+    window: Vec<f32>,
+    /// Interleaved source frames, starting at frame `input_start`
+    input: VecDeque<f32>,
+    input_start: usize,
+    source_done: bool,
+    /// Index of the next grain
+    grain: usize,
+    /// Overlap-added grains, the first half is complete once the next grain is added
+    output: Vec<f32>,
+    /// Samples of the first half of `output` already played
+    output_position: usize,
+    finished: bool,
+}
+
+impl<S> PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, settings: &PitchSettings) -> Self {
+        let channels = source.channels() as usize;
+        let sample_rate = source.sample_rate();
+        let grain_len = ((sample_rate * GRAIN_MS / 1000) as usize & !1).max(2);
+
+        // A periodic Hann window, whose halves add up to exactly 1 when overlapped
+        let window = (0..grain_len)
+            .map(|frame| 0.5 - 0.5 * (2.0 * PI * frame as f32 / grain_len as f32).cos())
+            .collect();
+
+        let mut pitch_shift = PitchShift {
+            source,
+            channels,
+            sample_rate,
+            pitch: settings.pitch_ratio(),
+            speed: settings.speed(),
+            grain_len,
+            window,
+            input: VecDeque::new(),
+            input_start: 0,
+            source_done: false,
+            grain: 0,
+            output: vec![0.0; grain_len * channels],
+            output_position: 0,
+            finished: false,
+        };
+        pitch_shift.add_grain();
+
+        pitch_shift
+    }
+
+    fn hop(&self) -> usize {
+        self.grain_len / 2
+    }
+
+    fn input_frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Reads source frames until `frame` is buffered, or the source runs out.
+    fn fill_input(&mut self, frame: usize) {
+        while !self.source_done && self.input_start + self.input_frames() <= frame {
+            for channel in 0..self.channels {
+                match self.source.next() {
+                    Some(sample) => self.input.push_back(sample),
+                    None => {
+                        self.source_done = true;
+                        // Pad a partial frame so the buffer stays interleaved
+                        if channel > 0 {
+                            self.input
+                                .extend(std::iter::repeat_n(0.0, self.channels - channel));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn input_sample(&self, frame: usize, channel: usize) -> f32 {
+        frame
+            .checked_sub(self.input_start)
+            .and_then(|frame| self.input.get(frame * self.channels + channel))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// Overlap-adds the next grain into the output. Returns false once the grains have
+    /// moved past the end of the source.
+    fn add_grain(&mut self) -> bool {
+        let start = self.grain as f32 * self.hop() as f32 * self.speed;
+        let first_frame = start as usize;
+        let last_frame = (start + self.grain_len as f32 * self.pitch) as usize + 1;
+        self.fill_input(last_frame);
+
+        if self.source_done && first_frame >= self.input_start + self.input_frames() {
+            return false;
+        }
+
+        for frame in 0..self.grain_len {
+            let position = start + frame as f32 * self.pitch;
+            let index = position as usize;
+            let fraction = position - index as f32;
+
+            for channel in 0..self.channels {
+                // Linear interpolation between the two nearest source frames
+                let sample = self.input_sample(index, channel) * (1.0 - fraction)
+                    + self.input_sample(index + 1, channel) * fraction;
+                self.output[frame * self.channels + channel] += sample * self.window[frame];
+            }
+        }
+
+        // Later grains start further in, so anything before this one is no longer needed
+        let next_start = (self.grain + 1) as f32 * self.hop() as f32 * self.speed;
+        let unused = (next_start as usize)
+            .saturating_sub(self.input_start)
+            .min(self.input_frames());
+        self.input.drain(..unused * self.channels);
+        self.input_start += unused;
+
+        self.grain += 1;
+        true
+    }
+}
+
+impl<S> Iterator for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let half = self.hop() * self.channels;
+
+        if self.output_position == half {
+            if self.finished {
+                return None;
+            }
+
+            // Move the second half forward and add the grain that completes it
+            self.output.copy_within(half.., 0);
+            self.output[half..].fill(0.0);
+            self.output_position = 0;
+
+            if !self.add_grain() {
+                // Play out the last grain's tail, then stop
+                self.finished = true;
+            }
+        }
+
+        let sample = self.output[self.output_position];
+        self.output_position += 1;
+
+        Some(sample)
+    }
+}
+
+impl<S> Source for PitchShift<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source
+            .total_duration()
+            .map(|duration| duration.div_f32(self.speed))
+    }
+}
@@ -0,0 +1,239 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{BoxedSource, Effect, Tail};
+
+/// Freeverb's delay line lengths, tuned for 44.1 kHz
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+const TUNING_SAMPLE_RATE: f32 = 44_100.0;
+/// Offset between channels, so stereo sounds get a wider reverb
+const STEREO_SPREAD: usize = 23;
+/// Keeps the eight parallel combs from clipping
+const INPUT_GAIN: f32 = 0.015;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+const MAX_TAIL_SECS: u64 = 20;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ReverbPreset {
+    Room,
+    Hall,
+    Bathroom,
+    Stadium,
+}
+
+impl ReverbPreset {
+    /// How much longer than Freeverb's default room the delay lines are
+    fn size(&self) -> f32 {
+        match self {
+            ReverbPreset::Room => 1.0,
+            ReverbPreset::Hall => 1.6,
+            ReverbPreset::Bathroom => 0.5,
+            ReverbPreset::Stadium => 2.5,
+        }
+    }
+
+    /// How quickly high frequencies die out, tiles reflect a lot more than seats
+    fn damping(&self) -> f32 {
+        match self {
+            ReverbPreset::Room => 0.5,
+            ReverbPreset::Hall => 0.3,
+            ReverbPreset::Bathroom => 0.1,
+            ReverbPreset::Stadium => 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ReverbSettings {
+    pub preset: ReverbPreset,
+    /// Level of the reverb, 0.0 to 1.0
+    pub wet: f32,
+    /// Level of the original sound, 0.0 to 1.0
+    pub dry: f32,
+    /// How long the reverb rings out, 0.0 to 1.0
+    pub decay: f32,
+}
+
+impl Effect for ReverbSettings {
+    fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.wet)
+            && (0.0..=1.0).contains(&self.dry)
+            && (0.0..=1.0).contains(&self.decay)
+    }
+
+    fn apply(&self, source: BoxedSource) -> BoxedSource {
+        Box::new(Reverb::new(source, self))
+    }
+}
+
+/// A feedback comb filter with a low-pass in its loop.
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Self {
+        Comb {
+            buffer: vec![0.0; len.max(1)],
+            position: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer[self.position] = input + self.filter_store * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        output
+    }
+}
+
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Self {
+        Allpass {
+            buffer: vec![0.0; len.max(1)],
+            position: 0,
+        }
+    }
+
+    #[inline]
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * ALLPASS_FEEDBACK;
+        self.position = (self.position + 1) % self.buffer.len();
+
+        delayed - input
+    }
+}
+
+/// The combs and allpasses of one channel
+struct ReverbChannel {
+    combs: Vec<Comb>,
+    allpasses: Vec<Allpass>,
+}
+
+/// Freeverb: eight parallel combs into four allpasses in series, per channel. Keeps playing
+/// after the sound ends until the reverb dies out.
+pub struct Reverb<S> {
+    source: S,
+    channels: Vec<ReverbChannel>,
+    channel: usize,
+    sample_rate: u32,
+    feedback: f32,
+    damping: f32,
+    wet: f32,
+    dry: f32,
+    tail: Tail,
+}
+
+impl<S> Reverb<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, settings: &ReverbSettings) -> Self {
+        let channel_count = source.channels().max(1) as usize;
+        let sample_rate = source.sample_rate();
+        let scale = sample_rate as f32 / TUNING_SAMPLE_RATE * settings.preset.size();
+        let delay_len = |tuning: usize| (tuning as f32 * scale) as usize;
+
+        let channels = (0..channel_count)
+            .map(|channel| {
+                let spread = channel * STEREO_SPREAD;
+                ReverbChannel {
+                    combs: COMB_TUNINGS
+                        .iter()
+                        .map(|tuning| Comb::new(delay_len(tuning + spread)))
+                        .collect(),
+                    allpasses: ALLPASS_TUNINGS
+                        .iter()
+                        .map(|tuning| Allpass::new(delay_len(tuning + spread)))
+                        .collect(),
+                }
+            })
+            .collect();
+
+        // Freeverb maps its room size onto 0.7 to 0.98 of feedback
+        let feedback = 0.7 + settings.decay.clamp(0.0, 1.0) * 0.28;
+
+        // Number of trips around the longest comb before it falls under the threshold
+        let longest_comb = delay_len(COMB_TUNINGS[COMB_TUNINGS.len() - 1]);
+        let repeats = Tail::repeats(feedback);
+        let tail_frames =
+            (longest_comb * repeats).min(sample_rate as usize * MAX_TAIL_SECS as usize);
+
+        Reverb {
+            source,
+            channels,
+            channel: 0,
+            sample_rate,
+            feedback,
+            damping: settings.preset.damping(),
+            wet: settings.wet.clamp(0.0, 1.0),
+            dry: settings.dry.clamp(0.0, 1.0),
+            tail: Tail::new(tail_frames * channel_count),
+        }
+    }
+}
+
+impl<S> Iterator for Reverb<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let dry = self.tail.next(&mut self.source)?;
+
+        let channel = &mut self.channels[self.channel];
+        let input = dry * INPUT_GAIN;
+        let mut wet: f32 = channel
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(input, self.feedback, self.damping))
+            .sum();
+        for allpass in channel.allpasses.iter_mut() {
+            wet = allpass.process(wet);
+        }
+        self.channel = (self.channel + 1) % self.channels.len();
+
+        Some(dry * self.dry + wet * self.wet)
+    }
+}
+
+impl<S> Source for Reverb<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // The tail keeps going after the source's last frame
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels.len() as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        let tail = self.tail.duration(self.sample_rate, self.channels());
+        self.source.total_duration().map(|duration| duration + tail)
+    }
+}
@@ -44,14 +44,46 @@ pub enum SettingsError {
     LoadSoundsFolder,
     #[error("Failed to serialize settings state")]
     SerializeSettings,
-    #[error("Failed to deserialize settings state")]
-    DeserializeSettings,
     #[error("Failed to write settings file")]
     WriteSettings,
     #[error("Failed to load audio devices")]
     LoadAudioDevices(#[from] SerializableDevicesError),
     #[error("Failed to get audio device names")]
     GetDeviceNames(#[from] SerializableDeviceNamesError),
+    #[error("Invalid keybind: {0}")]
+    InvalidKeybind(String),
+    #[error("Keybind is already used by {}", .0.join(", "))]
+    KeybindConflict(Vec<String>),
+    #[error("Profile {0} does not exist")]
+    ProfileNotFound(String),
+    #[error("Profile {0} already exists")]
+    ProfileAlreadyExists(String),
+    #[error("Profile names can't be empty")]
+    InvalidProfileName,
+    #[error("Can't delete the only profile")]
+    DeleteLastProfile,
+    #[error("Tags can't be empty")]
+    InvalidTag,
+    #[error("Effect settings are out of range")]
+    InvalidEffectSettings,
+    #[error("Target loudness must be between -40 and 0 LUFS")]
+    InvalidTargetLoudness,
+    #[error("Volume must be between 0 and 200%")]
+    InvalidVolume,
+    #[error("A sound's end point must come after its start point")]
+    InvalidTrim,
+    #[error("Fades can't be longer than 10 seconds")]
+    InvalidFade,
+    #[error("Max polyphony must be at least 1")]
+    InvalidPolyphony,
+    #[error("Group names can't be empty")]
+    InvalidGroupName,
+    #[error("Group weights must be above 0")]
+    InvalidGroupWeight,
+    #[error("Macro names can't be empty")]
+    InvalidMacroName,
+    #[error("Gaps and overlaps between steps can't be longer than a minute")]
+    InvalidMacroOffset,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -60,6 +92,24 @@ pub enum SoundsError {
     LoadSoundFile,
     #[error("Failed to open sound file path")]
     OpenSoundFilePath,
+    #[error("Failed to decode sound file")]
+    DecodeSoundFile,
+    #[error("Failed to find audio output device")]
+    FindOutputDevice,
+    #[error("Failed to open audio output stream")]
+    OpenOutputStream,
+    #[error("Audio engine is not running")]
+    EngineUnavailable,
+    #[error("No sound is playing with that ID")]
+    PlaybackNotFound,
+    #[error("The sound is silent")]
+    SilentSound,
+    #[error("Silence threshold must be between -96 and 0 dB")]
+    InvalidSilenceThreshold,
+    #[error("Macro {0} does not exist")]
+    MacroNotFound(String),
+    #[error("Track is empty")]
+    EmptyTrack,
 }
 
 #[derive(Debug, Error, Serialize)]
@@ -0,0 +1,98 @@
+use rodio::Source;
+use std::time::Duration;
+
+use crate::effects::BoxedSource;
+use crate::settings::KeybindSetting;
+
+/// Longest fade that can be set, for a sound or for stopping sounds
+pub const MAX_FADE_MS: u64 = 10_000;
+
+/// Fades the setting's sound in at its start and out at its end. `duration` is how long the
+/// sound is once trimmed.
+pub fn apply(source: BoxedSource, setting: &KeybindSetting, duration: Duration) -> BoxedSource {
+    let source: BoxedSource = match setting.fade_in_ms {
+        0 => source,
+        fade_in_ms => Box::new(source.fade_in(Duration::from_millis(fade_in_ms))),
+    };
+
+    match setting.fade_out_ms {
+        0 => source,
+        fade_out_ms => Box::new(FadeOut::new(
+            source,
+            duration,
+            Duration::from_millis(fade_out_ms),
+        )),
+    }
+}
+
+/// Number of interleaved samples that make up `duration` of a source.
+pub fn samples(duration: Duration, sample_rate: u32, channels: u16) -> usize {
+    (duration.as_secs_f64() * sample_rate as f64) as usize * channels.max(1) as usize
+}
+
+/// Fades a source out over the last `fade` of its `duration`.
+pub struct FadeOut<S> {
+    source: S,
+    position: usize,
+    fade_start: usize,
+    fade_len: usize,
+}
+
+impl<S> FadeOut<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(source: S, duration: Duration, fade: Duration) -> Self {
+        let (sample_rate, channels) = (source.sample_rate(), source.channels());
+        let fade = fade.min(duration);
+
+        FadeOut {
+            fade_start: samples(duration - fade, sample_rate, channels),
+            fade_len: samples(fade, sample_rate, channels).max(1),
+            position: 0,
+            source,
+        }
+    }
+}
+
+impl<S> Iterator for FadeOut<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let faded = self.position.saturating_sub(self.fade_start);
+        if faded >= self.fade_len {
+            return None;
+        }
+
+        let sample = self.source.next()?;
+        self.position += 1;
+
+        Some(sample * (1.0 - faded as f32 / self.fade_len as f32))
+    }
+}
+
+impl<S> Source for FadeOut<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        // Ends with the fade, which may be before the source's last frame
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -55,18 +55,23 @@ pub fn get_sounds_folder_path() -> Result<PathBuf, FilesError> {
     }
 }
 
+pub fn get_settings_file_path() -> Result<PathBuf, FilesError> {
+    Ok(get_sounds_folder_path()?.join("settings.json"))
+}
+
 pub fn get_settings() -> Result<SettingsFile, FilesError> {
     println!("Getting settings file");
 
-    let settings_file_path = get_sounds_folder_path()?.join("settings.json");
+    let settings_file_path = get_settings_file_path()?;
     if !settings_file_path.exists() {
         fs::write(&settings_file_path, "").map_err(|_| FilesError::CreateSettingsFile)?;
     }
 
     let settings_content =
         file::read_string(&settings_file_path).map_err(|_| FilesError::ReadSettingsFile)?;
-    let settings_file: SettingsFile =
+    let mut settings_file: SettingsFile =
         serde_json::from_str(&settings_content).map_err(|_| FilesError::DeserializeSettingsFile)?;
+    settings_file.migrate();
 
     Ok(settings_file)
 }
@@ -83,9 +88,7 @@ pub fn create_sounds_folder() -> Result<(), FilesError> {
 }
 
 pub fn create_settings_file() -> Result<(), FilesError> {
-    let settings_file_path = get_sounds_folder_path()
-        .expect("Failed to get sounds folder path")
-        .join("settings.json");
+    let settings_file_path = get_settings_file_path().expect("Failed to get sounds folder path");
 
     let host = cpal::default_host();
     let default_input_device = host
@@ -99,11 +102,7 @@ pub fn create_settings_file() -> Result<(), FilesError> {
         .name()
         .expect("Failed to get default output device name");
 
-    let default_settings = SettingsFile {
-        input_device: default_input_device,
-        output_device: default_output_device,
-        noise_settings: Vec::new(),
-    };
+    let default_settings = SettingsFile::new(default_input_device, default_output_device);
 
     let settings_string =
         serde_json::to_string_pretty(&default_settings).expect("Unable to convert to JSON string");
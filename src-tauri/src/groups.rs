@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+use crate::errors::SettingsError;
+use crate::keybind::{self, Keybind};
+use crate::settings::{validate_keybind, write_settings_file, KeybindSetting, SettingsFile};
+use crate::SettingsState;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PickMode {
+    /// Never the same sound twice in a row, unless it's the only one
+    #[default]
+    Random,
+    /// Each sound in turn, in the order they were added
+    RoundRobin,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupMember {
+    pub filename: String,
+    /// How likely this sound is to be picked compared to the others, in random mode
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// A pool of sounds on one keybind, which plays one of them on each press.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SoundGroup {
+    pub name: String,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub keybind: Option<Keybind>,
+    pub members: Vec<GroupMember>,
+    #[serde(default)]
+    pub pick_mode: PickMode,
+    /// Index of the member played last, so it isn't repeated
+    #[serde(skip)]
+    last_picked: Option<usize>,
+}
+
+impl SoundGroup {
+    /// Picks the member to play next.
+    fn pick(&mut self) -> Option<&GroupMember> {
+        if self.members.is_empty() {
+            return None;
+        }
+
+        let index = match self.pick_mode {
+            PickMode::RoundRobin => self
+                .last_picked
+                .map_or(0, |last_picked| (last_picked + 1) % self.members.len()),
+            PickMode::Random => {
+                let candidates: Vec<usize> = (0..self.members.len())
+                    .filter(|index| self.members.len() == 1 || Some(*index) != self.last_picked)
+                    .collect();
+                let total_weight: f32 = candidates
+                    .iter()
+                    .map(|index| self.members[*index].weight)
+                    .sum();
+
+                let mut roll = fastrand::f32() * total_weight;
+                *candidates
+                    .iter()
+                    .find(|index| {
+                        roll -= self.members[**index].weight;
+                        roll < 0.0
+                    })
+                    // Rounding can leave a sliver of weight at the end
+                    .unwrap_or(&candidates[candidates.len() - 1])
+            }
+        };
+        self.last_picked = Some(index);
+
+        self.members.get(index)
+    }
+}
+
+/// Picks a sound from the group bound to `keybind`, using the active profile's setting for
+/// it if there is one.
+pub fn resolve(settings_file: &mut SettingsFile, keybind: &Keybind) -> Option<KeybindSetting> {
+    let filename = settings_file
+        .sound_groups
+        .iter_mut()
+        .find(|group| group.keybind.as_ref() == Some(keybind))?
+        .pick()?
+        .filename
+        .clone();
+
+    let setting = settings_file.setting_or_default(&filename);
+
+    Some(setting)
+}
+
+/// Creates a group, or replaces the one with the same name.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_sound_group(
+    name: String,
+    keybind: String,
+    members: Vec<GroupMember>,
+    pick_mode: PickMode,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving sound group {}", name);
+
+    let name = name.trim().to_owned();
+    if name.is_empty() {
+        return Err(SettingsError::InvalidGroupName);
+    }
+    if members
+        .iter()
+        .any(|member| !member.weight.is_finite() || member.weight <= 0.0)
+    {
+        return Err(SettingsError::InvalidGroupWeight);
+    }
+
+    let keybind = keybind::parse_optional(&keybind)?;
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    validate_keybind(&settings_state, &name, None, keybind.as_ref())?;
+
+    let group = SoundGroup {
+        name,
+        keybind,
+        members,
+        pick_mode,
+        last_picked: None,
+    };
+    match settings_state
+        .sound_groups
+        .iter_mut()
+        .find(|existing_group| existing_group.name == group.name)
+    {
+        Some(existing_group) => *existing_group = group,
+        None => settings_state.sound_groups.push(group),
+    }
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn delete_sound_group(
+    name: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Deleting sound group {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state
+        .sound_groups
+        .retain(|group| group.name != name);
+
+    write_settings_file(&settings_state)
+}
@@ -0,0 +1,307 @@
+use rdev::Key;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::errors::SettingsError;
+
+/// Modifiers in the order they're rendered, left and right variants are treated the same
+const MODIFIERS: &[(Key, &str)] = &[
+    (Key::ControlLeft, "Ctrl"),
+    (Key::ShiftLeft, "Shift"),
+    (Key::Alt, "Alt"),
+    (Key::AltGr, "AltGr"),
+    (Key::MetaLeft, "Meta"),
+];
+
+const KEY_NAMES: &[(Key, &str)] = &[
+    (Key::KeyA, "A"),
+    (Key::KeyB, "B"),
+    (Key::KeyC, "C"),
+    (Key::KeyD, "D"),
+    (Key::KeyE, "E"),
+    (Key::KeyF, "F"),
+    (Key::KeyG, "G"),
+    (Key::KeyH, "H"),
+    (Key::KeyI, "I"),
+    (Key::KeyJ, "J"),
+    (Key::KeyK, "K"),
+    (Key::KeyL, "L"),
+    (Key::KeyM, "M"),
+    (Key::KeyN, "N"),
+    (Key::KeyO, "O"),
+    (Key::KeyP, "P"),
+    (Key::KeyQ, "Q"),
+    (Key::KeyR, "R"),
+    (Key::KeyS, "S"),
+    (Key::KeyT, "T"),
+    (Key::KeyU, "U"),
+    (Key::KeyV, "V"),
+    (Key::KeyW, "W"),
+    (Key::KeyX, "X"),
+    (Key::KeyY, "Y"),
+    (Key::KeyZ, "Z"),
+    (Key::Num0, "0"),
+    (Key::Num1, "1"),
+    (Key::Num2, "2"),
+    (Key::Num3, "3"),
+    (Key::Num4, "4"),
+    (Key::Num5, "5"),
+    (Key::Num6, "6"),
+    (Key::Num7, "7"),
+    (Key::Num8, "8"),
+    (Key::Num9, "9"),
+    (Key::F1, "F1"),
+    (Key::F2, "F2"),
+    (Key::F3, "F3"),
+    (Key::F4, "F4"),
+    (Key::F5, "F5"),
+    (Key::F6, "F6"),
+    (Key::F7, "F7"),
+    (Key::F8, "F8"),
+    (Key::F9, "F9"),
+    (Key::F10, "F10"),
+    (Key::F11, "F11"),
+    (Key::F12, "F12"),
+    (Key::Kp0, "Numpad0"),
+    (Key::Kp1, "Numpad1"),
+    (Key::Kp2, "Numpad2"),
+    (Key::Kp3, "Numpad3"),
+    (Key::Kp4, "Numpad4"),
+    (Key::Kp5, "Numpad5"),
+    (Key::Kp6, "Numpad6"),
+    (Key::Kp7, "Numpad7"),
+    (Key::Kp8, "Numpad8"),
+    (Key::Kp9, "Numpad9"),
+    (Key::KpPlus, "NumpadPlus"),
+    (Key::KpMinus, "NumpadMinus"),
+    (Key::KpMultiply, "NumpadMultiply"),
+    (Key::KpDivide, "NumpadDivide"),
+    (Key::KpReturn, "NumpadEnter"),
+    (Key::KpDelete, "NumpadDelete"),
+    (Key::UpArrow, "Up"),
+    (Key::DownArrow, "Down"),
+    (Key::LeftArrow, "Left"),
+    (Key::RightArrow, "Right"),
+    (Key::Space, "Space"),
+    (Key::Tab, "Tab"),
+    (Key::Return, "Enter"),
+    (Key::Escape, "Escape"),
+    (Key::Backspace, "Backspace"),
+    (Key::Delete, "Delete"),
+    (Key::Insert, "Insert"),
+    (Key::Home, "Home"),
+    (Key::End, "End"),
+    (Key::PageUp, "PageUp"),
+    (Key::PageDown, "PageDown"),
+    (Key::PrintScreen, "PrintScreen"),
+    (Key::ScrollLock, "ScrollLock"),
+    (Key::Pause, "Pause"),
+    (Key::NumLock, "NumLock"),
+    (Key::CapsLock, "CapsLock"),
+    (Key::Function, "Fn"),
+    (Key::BackQuote, "`"),
+    (Key::Minus, "-"),
+    (Key::Equal, "="),
+    (Key::LeftBracket, "["),
+    (Key::RightBracket, "]"),
+    (Key::SemiColon, ";"),
+    (Key::Quote, "'"),
+    (Key::BackSlash, "\\"),
+    (Key::IntlBackslash, "IntlBackslash"),
+    (Key::Comma, ","),
+    (Key::Dot, "."),
+    (Key::Slash, "/"),
+];
+
+/// Extra spellings accepted when parsing, on top of the rendered names
+const KEY_ALIASES: &[(Key, &str)] = &[
+    (Key::ControlLeft, "Control"),
+    (Key::MetaLeft, "Win"),
+    (Key::MetaLeft, "Super"),
+    (Key::MetaLeft, "Cmd"),
+    (Key::Escape, "Esc"),
+    (Key::Return, "Return"),
+    (Key::Delete, "Del"),
+    (Key::Insert, "Ins"),
+    (Key::UpArrow, "ArrowUp"),
+    (Key::DownArrow, "ArrowDown"),
+    (Key::LeftArrow, "ArrowLeft"),
+    (Key::RightArrow, "ArrowRight"),
+];
+
+/// Maps both variants of a modifier to the one stored in keybinds, or `None` for other keys.
+pub fn modifier(key: Key) -> Option<Key> {
+    match key {
+        Key::ControlLeft | Key::ControlRight => Some(Key::ControlLeft),
+        Key::ShiftLeft | Key::ShiftRight => Some(Key::ShiftLeft),
+        Key::MetaLeft | Key::MetaRight => Some(Key::MetaLeft),
+        Key::Alt => Some(Key::Alt),
+        Key::AltGr => Some(Key::AltGr),
+        _ => None,
+    }
+}
+
+/// A single key as the UI shows it, with both variants of a modifier named the same.
+pub fn display_key(key: Key) -> String {
+    key_name(modifier(key).unwrap_or(key))
+}
+
+fn key_name(key: Key) -> String {
+    MODIFIERS
+        .iter()
+        .chain(KEY_NAMES)
+        .find(|(named_key, _)| *named_key == key)
+        .map(|(_, name)| name.to_string())
+        .unwrap_or_else(|| match key {
+            Key::Unknown(code) => format!("Unknown({})", code),
+            _ => format!("{:?}", key),
+        })
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    let name = name.trim();
+
+    if let Some(code) = name
+        .strip_prefix("Unknown(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return code.parse().ok().map(Key::Unknown);
+    }
+
+    MODIFIERS
+        .iter()
+        .chain(KEY_NAMES)
+        .chain(KEY_ALIASES)
+        .find(|(_, key_name)| key_name.eq_ignore_ascii_case(name))
+        .map(|(key, _)| *key)
+}
+
+/// App-wide actions that are bound like sounds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Hotkey {
+    StopAll,
+    NextProfile,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    /// Starts or stops the queue
+    QueuePlayStop,
+    QueueNext,
+    QueuePrevious,
+}
+
+impl Hotkey {
+    pub const ALL: &'static [Hotkey] = &[
+        Hotkey::StopAll,
+        Hotkey::NextProfile,
+        Hotkey::VolumeUp,
+        Hotkey::VolumeDown,
+        Hotkey::Mute,
+        Hotkey::QueuePlayStop,
+        Hotkey::QueueNext,
+        Hotkey::QueuePrevious,
+    ];
+
+    /// Listed in place of a filename when a sound's keybind clashes with the hotkey
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hotkey::StopAll => "Stop all",
+            Hotkey::NextProfile => "Next profile",
+            Hotkey::VolumeUp => "Volume up",
+            Hotkey::VolumeDown => "Volume down",
+            Hotkey::Mute => "Mute",
+            Hotkey::QueuePlayStop => "Play or stop queue",
+            Hotkey::QueueNext => "Next track",
+            Hotkey::QueuePrevious => "Previous track",
+        }
+    }
+}
+
+/// A key combination such as `Ctrl+Shift+1`, pressed while the leader key is held.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Keybind {
+    pub modifiers: Vec<Key>,
+    pub key: Key,
+}
+
+impl Keybind {
+    pub fn new(modifiers: Vec<Key>, key: Key) -> Self {
+        // Keep modifiers in a canonical order so equal chords compare equal
+        let mut modifiers: Vec<Key> = modifiers.into_iter().filter_map(modifier).collect();
+        modifiers.sort_by_key(|modifier| {
+            MODIFIERS
+                .iter()
+                .position(|(named_modifier, _)| named_modifier == modifier)
+        });
+        modifiers.dedup();
+
+        Keybind { modifiers, key }
+    }
+}
+
+impl Display for Keybind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for modifier in &self.modifiers {
+            write!(f, "{}+", key_name(*modifier))?;
+        }
+        write!(f, "{}", key_name(self.key))
+    }
+}
+
+impl FromStr for Keybind {
+    type Err = SettingsError;
+
+    fn from_str(keybind: &str) -> Result<Self, Self::Err> {
+        let invalid = || SettingsError::InvalidKeybind(keybind.to_owned());
+
+        let (modifiers, key) = keybind.rsplit_once('+').unwrap_or(("", keybind));
+
+        let key = parse_key(key).ok_or_else(invalid)?;
+        let modifiers = modifiers
+            .split('+')
+            .filter(|name| !name.trim().is_empty())
+            .map(|name| parse_key(name).and_then(modifier).ok_or_else(invalid))
+            .collect::<Result<Vec<Key>, SettingsError>>()?;
+
+        Ok(Keybind::new(modifiers, key))
+    }
+}
+
+impl Serialize for Keybind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Keybind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let keybind = String::deserialize(deserializer)?;
+        keybind.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Parses an optional keybind coming from the UI, where "?" and "" mean unbound.
+pub fn parse_optional(keybind: &str) -> Result<Option<Keybind>, SettingsError> {
+    match keybind.trim() {
+        "" | "?" => Ok(None),
+        keybind => keybind.parse().map(Some),
+    }
+}
+
+/// Reads keybinds saved by older versions too, which used "?" for unbound keys.
+/// Anything unreadable is unbound rather than failing the whole settings file.
+pub fn deserialize_optional<'de, D>(deserializer: D) -> Result<Option<Keybind>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let keybind: Option<String> = Option::deserialize(deserializer)?;
+
+    Ok(keybind.and_then(|keybind| {
+        parse_optional(&keybind).unwrap_or_else(|error| {
+            eprintln!("Ignoring keybind from settings file: {}", error);
+            None
+        })
+    }))
+}
@@ -1,99 +1,188 @@
-use rdev::{listen, Event, EventType, Key, Keyboard, KeyboardState};
+use rdev::{listen, Event, EventType, Key};
 use std::cell::RefCell;
 use std::rc::Rc;
 use std::sync::Arc;
 use tauri::AppHandle;
 use tauri::Manager;
 
-use crate::files::get_sounds_folder_path;
-use crate::sounds;
-use crate::SettingsState;
+use crate::keybind::{self, Hotkey, Keybind};
+use crate::playback::{PlayMode, PlaybackId};
+use crate::{groups, macros, master_bus, profiles, queue, sounds};
+use crate::{AudioEngineState, SettingsState};
 
 #[derive(Debug, PartialEq)]
 enum KeybindState {
     WaitingForFirstKey,
-    FirstKeyPressed(Key),
-    KeyCombinationPressed(Key, Key),
+    /// The first key is held, along with any modifiers pressed since
+    FirstKeyPressed(Vec<Key>),
+    /// Stays here until the chord's key is released, so auto-repeat doesn't retrigger it
+    KeyCombinationPressed(Keybind),
 }
 
 struct KeybindListener {
     state: KeybindState,
-    keyboard: Keyboard,
     first_key: Key,
+    /// Sound started by the chord that's held down, if it's in hold mode
+    held_playback: Option<PlaybackId>,
 }
 
 impl KeybindListener {
     fn new(first_key: Key) -> Self {
         KeybindListener {
             state: KeybindState::WaitingForFirstKey,
-            keyboard: Keyboard::new().unwrap(),
             first_key,
+            held_playback: None,
         }
     }
 
     fn handle_event(&mut self, app_handle: Arc<AppHandle>, event: Event) {
         match event.event_type {
             EventType::KeyPress(key) => {
-                match self.state {
+                if self.state == KeybindState::WaitingForFirstKey {
+                    // Picks up leader key changes without having to restart the listener
+                    let app_state = app_handle.state::<SettingsState>();
+                    self.first_key = app_state.settings_state.lock().unwrap().leader_key;
+                }
+
+                match &mut self.state {
                     KeybindState::WaitingForFirstKey if key == self.first_key => {
-                        self.state = KeybindState::FirstKeyPressed(key);
+                        self.state = KeybindState::FirstKeyPressed(Vec::new());
                     }
-                    KeybindState::FirstKeyPressed(_) => {
-                        self.state = KeybindState::KeyCombinationPressed(self.first_key, key);
-
-                        // Convert Key enum into a string so we can match against keys in our settings
-                        let keyboard = &mut self.keyboard;
-                        if let Some(second_key) = keyboard.add(&EventType::KeyPress(key)) {
-                            // Load the settings from app state
-                            let app_state = app_handle.state::<SettingsState>();
-                            let mutex_settings = app_state.settings_state.lock().unwrap();
-                            let settings = &mutex_settings.noise_settings;
-
-                            match settings.iter().find(|setting| {
-                                setting.keybind.to_uppercase() == second_key.to_uppercase()
-                            }) {
-                                Some(setting) => {
-                                    let sound_file_path = get_sounds_folder_path()
-                                        .unwrap()
-                                        .join(&setting.filename)
-                                        .into_os_string()
-                                        .into_string()
-                                        .unwrap();
-
-                                    sounds::make_some_noise(
-                                        sound_file_path,
-                                        setting.user_volume,
-                                        setting.listener_volume,
-                                        mutex_settings.input_device.clone(),
-                                        mutex_settings.output_device.clone(),
-                                    )
-                                    .expect("Failed to play sound")
-                                }
-                                None => {
-                                    dbg!("No sound found for key {:?}", second_key.clone());
-                                }
-                            }
+                    // Holding the first key down repeats its key press
+                    KeybindState::FirstKeyPressed(_) if key == self.first_key => {}
+                    KeybindState::FirstKeyPressed(modifiers) => match keybind::modifier(key) {
+                        Some(modifier) if !modifiers.contains(&modifier) => {
+                            modifiers.push(modifier)
                         }
-
-                        // reset state after our sound is played
-                        self.state = KeybindState::WaitingForFirstKey;
-                    }
+                        Some(_) => {}
+                        None => {
+                            let keybind = Keybind::new(modifiers.clone(), key);
+                            self.held_playback = self.trigger(&app_handle, &keybind);
+                            self.state = KeybindState::KeyCombinationPressed(keybind);
+                        }
+                    },
                     _ => {}
                 }
             }
             EventType::KeyRelease(key) if key == self.first_key => {
                 self.state = KeybindState::WaitingForFirstKey
             }
+            EventType::KeyRelease(key) => match &mut self.state {
+                KeybindState::FirstKeyPressed(modifiers) => {
+                    modifiers.retain(|modifier| Some(*modifier) != keybind::modifier(key));
+                }
+                KeybindState::KeyCombinationPressed(keybind) => {
+                    let mut modifiers = keybind.modifiers.clone();
+                    modifiers.retain(|modifier| Some(*modifier) != keybind::modifier(key));
+
+                    // Releasing any part of the chord lets the next one be pressed
+                    if key == keybind.key || modifiers.len() != keybind.modifiers.len() {
+                        self.state = KeybindState::FirstKeyPressed(modifiers);
+                    }
+                }
+                _ => {}
+            },
             _ => {}
         }
+
+        // Hold mode sounds stop as soon as their chord is let go
+        if !matches!(self.state, KeybindState::KeyCombinationPressed(_)) {
+            if let Some(id) = self.held_playback.take() {
+                app_handle.state::<AudioEngineState>().playbacks.stop(id);
+            }
+        }
+    }
+
+    /// Returns the playback to stop once the keybind is released, for sounds in hold mode.
+    fn trigger(&self, app_handle: &AppHandle, keybind: &Keybind) -> Option<PlaybackId> {
+        // Load the settings from app state
+        let app_state = app_handle.state::<SettingsState>();
+        let mut mutex_settings = app_state.settings_state.lock().unwrap();
+
+        let engine_state = app_handle.state::<AudioEngineState>();
+
+        if let Some(hotkey) = Hotkey::ALL
+            .iter()
+            .find(|hotkey| mutex_settings.hotkey(**hotkey) == Some(keybind))
+        {
+            match hotkey {
+                // Silences both the listener and user outputs
+                Hotkey::StopAll => engine_state.playbacks.stop_all(),
+                Hotkey::NextProfile => {
+                    if let Err(error) = profiles::activate_next(app_handle, &mut mutex_settings) {
+                        eprintln!("Failed to switch profile: {}", error);
+                    }
+                }
+                Hotkey::VolumeUp | Hotkey::VolumeDown => {
+                    let steps = if *hotkey == Hotkey::VolumeUp {
+                        1.0
+                    } else {
+                        -1.0
+                    };
+                    if let Err(error) =
+                        master_bus::step_master_volumes(app_handle, &mut mutex_settings, steps)
+                    {
+                        eprintln!("Failed to change the master volume: {}", error);
+                    }
+                }
+                Hotkey::Mute => {
+                    if let Err(error) =
+                        master_bus::toggle_master_mute(app_handle, &mut mutex_settings)
+                    {
+                        eprintln!("Failed to mute: {}", error);
+                    }
+                }
+                Hotkey::QueuePlayStop | Hotkey::QueueNext | Hotkey::QueuePrevious => {
+                    let result = match hotkey {
+                        Hotkey::QueuePlayStop => queue::toggle(app_handle, &mutex_settings),
+                        Hotkey::QueueNext => queue::skip(app_handle, &mutex_settings),
+                        _ => queue::previous(app_handle, &mutex_settings),
+                    };
+                    if let Err(error) = result {
+                        eprintln!("Failed to play the queue: {}", error);
+                    }
+                }
+            }
+            return None;
+        }
+
+        if let Some(sound_macro) = macros::find_by_keybind(&mutex_settings, keybind) {
+            if let Err(error) = macros::start(app_handle, &mutex_settings, sound_macro) {
+                eprintln!("Failed to play macro: {}", error);
+            }
+            return None;
+        }
+
+        // Groups pick one of their sounds each time they're triggered
+        let setting = groups::resolve(&mut mutex_settings, keybind).or_else(|| {
+            mutex_settings
+                .active_profile()
+                .active_noise_settings()
+                .find(|setting| setting.keybind.as_ref() == Some(keybind))
+                .cloned()
+        });
+
+        match setting {
+            Some(setting) => match sounds::trigger_sound(app_handle, &mutex_settings, &setting) {
+                Ok(playback) => playback.filter(|_| setting.play_mode == PlayMode::Hold),
+                Err(error) => {
+                    eprintln!("Failed to play sound: {}", error);
+                    None
+                }
+            },
+            None => {
+                println!("No sound found for keybind {}", keybind);
+                None
+            }
+        }
     }
 }
 
 pub fn run_listener(app_handle: AppHandle) {
-    // TODO: Make this customizable by user
-    const FIRST_KEY: Key = Key::Alt;
+    let app_state = app_handle.state::<SettingsState>();
+    let first_key = app_state.settings_state.lock().unwrap().leader_key;
 
-    let listener = Rc::new(RefCell::new(KeybindListener::new(FIRST_KEY)));
+    let listener = Rc::new(RefCell::new(KeybindListener::new(first_key)));
     let app_handle = Arc::new(app_handle);
 
     if let Err(error) = listen(move |event| {
@@ -101,6 +190,6 @@ pub fn run_listener(app_handle: AppHandle) {
         let app_handle = Arc::clone(&app_handle);
         listener.handle_event(app_handle, event);
     }) {
-        dbg!("Error: {:?}", error);
+        eprintln!("Failed to listen for keyboard events: {:?}", error);
     }
 }
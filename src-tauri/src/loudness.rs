@@ -0,0 +1,442 @@
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+use tauri::{AppHandle, Manager, State};
+
+use crate::effects::eq::{Biquad, FilterBank};
+use crate::errors::SettingsError;
+use crate::files::get_sounds_folder_path;
+use crate::settings::write_settings_file;
+use crate::SettingsState;
+
+const CACHE_FILE_NAME: &str = "loudness.json";
+
+/// Auto-gain never pushes a sound's true peak above this
+const TRUE_PEAK_CEILING_DB: f32 = -1.0;
+pub const MIN_TARGET_LUFS: f32 = -40.0;
+pub const MAX_TARGET_LUFS: f32 = 0.0;
+
+// EBU R128 gating
+const BLOCK_MS: u32 = 400;
+const BLOCKS_PER_STEP: usize = 4;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+
+/// Taps per phase of the 4x oversampling filter used for true peak
+const TRUE_PEAK_TAPS: usize = 12;
+const OVERSAMPLING: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LoudnessAnalysis {
+    /// `None` when the file is too quiet to measure or shorter than one 400 ms gating block
+    pub integrated_lufs: Option<f32>,
+    pub true_peak_db: f32,
+    // Used to tell whether the file changed since it was analyzed
+    modified_secs: u64,
+    size: u64,
+}
+
+impl LoudnessAnalysis {
+    /// The gain that brings the sound to `target_lufs`, held back if it would clip.
+    fn auto_gain(&self, target_lufs: f32) -> f32 {
+        match self.integrated_lufs {
+            Some(integrated_lufs) => {
+                let gain_db =
+                    (target_lufs - integrated_lufs).min(TRUE_PEAK_CEILING_DB - self.true_peak_db);
+                10f32.powf(gain_db / 20.0)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Loudness of every file in the sounds folder, cached in loudness.json next to settings.json
+/// so files are only analyzed again when they change.
+#[derive(Debug)]
+pub struct LoudnessState {
+    analyses: Mutex<HashMap<String, LoudnessAnalysis>>,
+    queue: Mutex<AnalysisQueue>,
+}
+
+/// File lists handed to `analyze_in_background` while a pass was already running.
+#[derive(Debug, Default)]
+struct AnalysisQueue {
+    running: bool,
+    // Only the latest list matters, since each one is the whole sounds folder
+    pending: Option<Vec<String>>,
+}
+
+impl Default for LoudnessState {
+    fn default() -> Self {
+        // A missing or broken cache only means the files get analyzed again
+        let analyses = get_sounds_folder_path()
+            .ok()
+            .and_then(|folder| fs::read_to_string(folder.join(CACHE_FILE_NAME)).ok())
+            .and_then(|cache| serde_json::from_str(&cache).ok())
+            .unwrap_or_default();
+
+        Self {
+            analyses: Mutex::new(analyses),
+            queue: Mutex::default(),
+        }
+    }
+}
+
+impl LoudnessState {
+    /// The gain to apply to `filename` before its volume, 1.0 when auto-gain is off or the
+    /// file hasn't been analyzed yet.
+    pub fn auto_gain(&self, target_lufs: Option<f32>, filename: &str) -> f32 {
+        let Some(target_lufs) = target_lufs else {
+            return 1.0;
+        };
+
+        self.analyses
+            .lock()
+            .unwrap()
+            .get(filename)
+            .map_or(1.0, |analysis| analysis.auto_gain(target_lufs))
+    }
+}
+
+fn write_cache(analyses: &HashMap<String, LoudnessAnalysis>) {
+    let result = get_sounds_folder_path()
+        .map_err(|error| error.to_string())
+        .and_then(|folder| {
+            let cache = serde_json::to_string_pretty(analyses).map_err(|e| e.to_string())?;
+            fs::write(folder.join(CACHE_FILE_NAME), cache).map_err(|e| e.to_string())
+        });
+
+    if let Err(error) = result {
+        eprintln!("Failed to write loudness cache: {}", error);
+    }
+}
+
+/// Returns the file's modified time and size, which identify this version of it.
+fn file_version(path: &Path) -> Option<(u64, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    let modified_secs = metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+
+    Some((modified_secs, metadata.len()))
+}
+
+/// Analyzes every file in `sound_files` that's new or changed since the last pass, on a
+/// thread of its own. Emits `loudness-analyzed` with the filename as each one finishes. If a
+/// pass is already running, `sound_files` is analyzed once it's done.
+pub fn analyze_in_background(app_handle: AppHandle, sound_files: Vec<String>) {
+    let loudness_state = app_handle.state::<LoudnessState>();
+    let mut queue = loudness_state.queue.lock().unwrap();
+    queue.pending = Some(sound_files);
+    if queue.running {
+        return;
+    }
+    queue.running = true;
+    drop(queue);
+
+    std::thread::spawn(move || {
+        let loudness_state = app_handle.state::<LoudnessState>();
+        loop {
+            let sound_files = {
+                let mut queue = loudness_state.queue.lock().unwrap();
+                match queue.pending.take() {
+                    Some(sound_files) => sound_files,
+                    None => {
+                        queue.running = false;
+                        return;
+                    }
+                }
+            };
+
+            analyze_files(&app_handle, &loudness_state, sound_files);
+        }
+    });
+}
+
+fn analyze_files(app_handle: &AppHandle, loudness_state: &LoudnessState, sound_files: Vec<String>) {
+    let Ok(sound_folder) = get_sounds_folder_path() else {
+        return;
+    };
+
+    // Forget files that were removed from the folder
+    loudness_state
+        .analyses
+        .lock()
+        .unwrap()
+        .retain(|filename, _| sound_files.contains(filename));
+
+    for filename in sound_files {
+        let path = sound_folder.join(&filename);
+        let Some((modified_secs, size)) = file_version(&path) else {
+            continue;
+        };
+
+        let up_to_date = loudness_state
+            .analyses
+            .lock()
+            .unwrap()
+            .get(&filename)
+            .is_some_and(|analysis| {
+                analysis.modified_secs == modified_secs && analysis.size == size
+            });
+        if up_to_date {
+            continue;
+        }
+
+        println!("Analyzing loudness of {}", filename);
+        match analyze_file(&path) {
+            Some((integrated_lufs, true_peak_db)) => {
+                loudness_state.analyses.lock().unwrap().insert(
+                    filename.clone(),
+                    LoudnessAnalysis {
+                        integrated_lufs,
+                        true_peak_db,
+                        modified_secs,
+                        size,
+                    },
+                );
+                let _ = app_handle.emit_all("loudness-analyzed", filename);
+            }
+            None => eprintln!("Failed to analyze loudness of {}", filename),
+        }
+    }
+
+    write_cache(&loudness_state.analyses.lock().unwrap());
+}
+
+/// Decodes a whole file and measures its integrated loudness and true peak.
+fn analyze_file(path: &Path) -> Option<(Option<f32>, f32)> {
+    let file = File::open(path).ok()?;
+    let decoder = Decoder::new(BufReader::new(file)).ok()?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+
+    let mut loudness_meter = LoudnessMeter::new(channels, sample_rate);
+    let mut true_peak_meter = TruePeakMeter::new(channels);
+    for sample in decoder.convert_samples::<f32>() {
+        loudness_meter.push(sample);
+        true_peak_meter.push(sample);
+    }
+
+    Some((
+        loudness_meter.integrated_lufs(),
+        true_peak_meter.true_peak_db(),
+    ))
+}
+
+/// The K-weighting filters from ITU-R BS.1770, for any sample rate
+fn k_weighting(sample_rate: u32) -> Vec<Biquad> {
+    let sample_rate = sample_rate as f32;
+
+    // High shelf modelling the acoustic effect of the head
+    let k = (PI * 1_681.974_5 / sample_rate).tan();
+    let q = 0.707_175_24;
+    let high_gain = 10f32.powf(3.999_843_9 / 20.0);
+    let band_gain = high_gain.powf(0.499_666_77);
+    let shelf = Biquad::normalized(
+        high_gain + band_gain * k / q + k * k,
+        2.0 * (k * k - high_gain),
+        high_gain - band_gain * k / q + k * k,
+        1.0 + k / q + k * k,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    );
+
+    // High-pass that ignores the lowest bass
+    let k = (PI * 38.135_47 / sample_rate).tan();
+    let q = 0.500_327;
+    // Unlike the usual high-pass, its numerator isn't scaled along with the denominator
+    let a0 = 1.0 + k / q + k * k;
+    let high_pass = Biquad::normalized(
+        a0,
+        -2.0 * a0,
+        a0,
+        a0,
+        2.0 * (k * k - 1.0),
+        1.0 - k / q + k * k,
+    );
+
+    vec![shelf, high_pass]
+}
+
+/// Integrated loudness as defined by EBU R128, in overlapping gated blocks.
+struct LoudnessMeter {
+    filters: FilterBank,
+    channel_weights: Vec<f64>,
+    channel: usize,
+    /// Frames in each quarter of a block
+    step_frames: usize,
+    step_frames_left: usize,
+    /// Weighted energy of the step being measured, and of the steps before it
+    step_energy: f64,
+    steps: Vec<f64>,
+}
+
+impl LoudnessMeter {
+    fn new(channels: usize, sample_rate: u32) -> Self {
+        // Surround channels count a bit more, LFE doesn't count at all
+        let channel_weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (6, 3) => 0.0,
+                (6, 4) | (6, 5) => 1.41,
+                _ => 1.0,
+            })
+            .collect();
+        let step_frames =
+            (sample_rate as usize * BLOCK_MS as usize / 1000 / BLOCKS_PER_STEP).max(1);
+
+        LoudnessMeter {
+            filters: FilterBank::new(k_weighting(sample_rate), channels as u16),
+            channel_weights,
+            channel: 0,
+            step_frames,
+            step_frames_left: step_frames,
+            step_energy: 0.0,
+            steps: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let weighted = self.filters.process(sample) as f64;
+        self.step_energy += weighted * weighted * self.channel_weights[self.channel];
+
+        self.channel += 1;
+        if self.channel == self.channel_weights.len() {
+            self.channel = 0;
+            self.step_frames_left -= 1;
+            if self.step_frames_left == 0 {
+                self.steps.push(self.step_energy);
+                self.step_energy = 0.0;
+                self.step_frames_left = self.step_frames;
+            }
+        }
+    }
+
+    fn integrated_lufs(&self) -> Option<f32> {
+        let block_frames = (self.step_frames * BLOCKS_PER_STEP) as f64;
+        let blocks: Vec<f64> = self
+            .steps
+            .windows(BLOCKS_PER_STEP)
+            .map(|steps| steps.iter().sum::<f64>() / block_frames)
+            .collect();
+
+        let loudness = |energy: f64| -0.691 + 10.0 * energy.log10();
+        let gated_mean = |gate: f64| {
+            let gated: Vec<f64> = blocks
+                .iter()
+                .copied()
+                .filter(|energy| loudness(*energy) > gate)
+                .collect();
+            (!gated.is_empty()).then(|| gated.iter().sum::<f64>() / gated.len() as f64)
+        };
+
+        let relative_gate = loudness(gated_mean(ABSOLUTE_GATE_LUFS)?) + RELATIVE_GATE_LU;
+        let integrated = loudness(gated_mean(relative_gate.max(ABSOLUTE_GATE_LUFS))?);
+
+        Some(integrated as f32)
+    }
+}
+
+/// Peak level between samples, found by oversampling 4x like the BS.1770 true peak meter.
+struct TruePeakMeter {
+    /// Polyphase interpolation filter, `TRUE_PEAK_TAPS` taps for each phase
+    phases: Vec<Vec<f32>>,
+    /// The last samples of each channel, newest first
+    history: Vec<Vec<f32>>,
+    channel: usize,
+    peak: f32,
+}
+
+impl TruePeakMeter {
+    fn new(channels: usize) -> Self {
+        let len = TRUE_PEAK_TAPS * OVERSAMPLING;
+        let center = (len - 1) as f32 / 2.0;
+
+        // Windowed sinc cutting off at the original Nyquist frequency
+        let taps: Vec<f32> = (0..len)
+            .map(|tap| {
+                let x = (tap as f32 - center) / OVERSAMPLING as f32;
+                let sinc = if x == 0.0 {
+                    1.0
+                } else {
+                    (PI * x).sin() / (PI * x)
+                };
+                let window = 0.5 - 0.5 * (2.0 * PI * tap as f32 / (len - 1) as f32).cos();
+                sinc * window
+            })
+            .collect();
+        let phases = (0..OVERSAMPLING)
+            .map(|phase| {
+                taps.iter()
+                    .skip(phase)
+                    .step_by(OVERSAMPLING)
+                    .copied()
+                    .collect()
+            })
+            .collect();
+
+        TruePeakMeter {
+            phases,
+            history: vec![vec![0.0; TRUE_PEAK_TAPS]; channels],
+            channel: 0,
+            peak: 0.0,
+        }
+    }
+
+    fn push(&mut self, sample: f32) {
+        let history = &mut self.history[self.channel];
+        history.rotate_right(1);
+        history[0] = sample;
+
+        for phase in &self.phases {
+            let interpolated: f32 = phase
+                .iter()
+                .zip(history.iter())
+                .map(|(tap, x)| tap * x)
+                .sum();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+        self.peak = self.peak.max(sample.abs());
+
+        self.channel = (self.channel + 1) % self.history.len();
+    }
+
+    fn true_peak_db(&self) -> f32 {
+        20.0 * self.peak.max(f32::EPSILON).log10()
+    }
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_loudness_analyses(
+    loudness_state: State<'_, LoudnessState>,
+) -> HashMap<String, LoudnessAnalysis> {
+    loudness_state.analyses.lock().unwrap().clone()
+}
+
+/// Turns auto-gain off when `target_lufs` is `None`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_target_lufs(
+    target_lufs: Option<f32>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Setting target loudness to {:?}", target_lufs);
+
+    if target_lufs.is_some_and(|target| !(MIN_TARGET_LUFS..=MAX_TARGET_LUFS).contains(&target)) {
+        return Err(SettingsError::InvalidTargetLoudness);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.target_lufs = target_lufs;
+
+    write_settings_file(&settings_state)
+}
@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::errors::{AppError, SettingsError, SoundsError};
+use crate::keybind::{self, Keybind};
+use crate::playback::{NewPlayback, PlayMode, PlaybackId, PlaybackKind};
+use crate::settings::{validate_keybind, write_settings_file, KeybindSetting, SettingsFile};
+use crate::{sounds, volume, AudioEngineState, SettingsState};
+
+/// Longest gap or overlap between two steps
+const MAX_OFFSET_MS: i64 = 60_000;
+/// How often a waiting macro checks whether it has been stopped
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MacroStep {
+    pub filename: String,
+    /// Time between the end of the previous step and the start of this one, negative
+    /// values overlap the two. For the first step, a delay after the macro is triggered
+    #[serde(default)]
+    pub offset_ms: i64,
+    /// Percent, applied on top of the sound's own volumes
+    #[serde(default = "default_step_volume")]
+    pub volume: f32,
+}
+
+fn default_step_volume() -> f32 {
+    100.0
+}
+
+/// A sequence of sounds played one after another from a single keybind.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SoundMacro {
+    pub name: String,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub keybind: Option<Keybind>,
+    pub steps: Vec<MacroStep>,
+}
+
+/// The active profile's setting for the step's sound, with the step's volume applied
+fn step_setting(settings_file: &SettingsFile, step: &MacroStep) -> KeybindSetting {
+    let mut setting = settings_file.setting_or_default(&step.filename);
+
+    // Percentages multiply like gains, as the volume curve is a power of them
+    setting.user_volume *= step.volume / 100.0;
+    setting.listener_volume *= step.volume / 100.0;
+    // The steps decide when each sound ends, so none of them loop
+    setting.play_mode = PlayMode::Overlap;
+
+    setting
+}
+
+/// Waits until `deadline`. Returns false if the macro was stopped in the meantime.
+fn wait_until(playback: &NewPlayback, deadline: Instant) -> bool {
+    loop {
+        if playback.is_stopped() {
+            return false;
+        }
+
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        thread::sleep(STOP_CHECK_INTERVAL.min(deadline - now));
+    }
+}
+
+fn play_step(app_handle: &AppHandle, playback: &NewPlayback, step: &MacroStep) -> Duration {
+    // Files are read without holding the settings, so keybinds and commands don't wait on
+    // the macro
+    let (setting, target_lufs) = {
+        let settings_state = app_handle.state::<SettingsState>();
+        let settings_file = settings_state.settings_state.lock().unwrap();
+        (
+            step_setting(&settings_file, step),
+            settings_file.target_lufs,
+        )
+    };
+
+    let voices = sounds::build_voices(app_handle, target_lufs, &setting);
+    let engine_state = app_handle.state::<AudioEngineState>();
+    let audio_engine = engine_state.audio_engine.lock().unwrap();
+
+    match (voices, audio_engine.as_ref()) {
+        (Ok((listener_source, user_source)), Some(audio_engine)) => {
+            // The macro's own playback already counts towards the overall limit
+            sounds::make_room_for(&engine_state.playbacks, &setting, None);
+            audio_engine.play(playback.voice(listener_source), playback.voice(user_source));
+        }
+        (Err(error), _) => eprintln!("Failed to play {}: {}", step.filename, error),
+        (_, None) => eprintln!(
+            "Failed to play {}: {}",
+            step.filename,
+            SoundsError::EngineUnavailable
+        ),
+    }
+    drop(audio_engine);
+
+    // A step that fails to play still takes up its time, so the rest stay in rhythm. One
+    // whose file can't be read at all has no known length and takes none.
+    sounds::sound_duration(&setting).unwrap_or_default()
+}
+
+/// Starts playing the macro in the background. All of its steps belong to one playback, so
+/// stopping it stops whatever is playing and cancels the steps still to come.
+pub fn start(
+    app_handle: &AppHandle,
+    settings_file: &SettingsFile,
+    sound_macro: &SoundMacro,
+) -> Result<PlaybackId, AppError> {
+    let engine_state = app_handle.state::<AudioEngineState>();
+    if engine_state.audio_engine.lock().unwrap().is_none() {
+        return Err(SoundsError::EngineUnavailable.into());
+    }
+
+    // Each macro still playing keeps a thread busy, so they count towards the overall limit
+    engine_state
+        .playbacks
+        .make_room(&sound_macro.name, None, settings_file.max_polyphony);
+    let playback = engine_state.playbacks.register(
+        sound_macro.name.clone(),
+        PlaybackKind::Sound,
+        None,
+        Duration::from_millis(settings_file.stop_fade_ms),
+    );
+    let id = playback.info.id;
+
+    let app_handle = app_handle.clone();
+    let steps = sound_macro.steps.clone();
+    thread::spawn(move || {
+        // Keeps the playback going between steps, until the last one has started
+        let _hold = playback.hold();
+        let started = Instant::now();
+        let mut previous_start = Duration::ZERO;
+        let mut previous_end = Duration::ZERO;
+
+        for step in steps.iter() {
+            let offset = Duration::from_millis(step.offset_ms.unsigned_abs());
+            let start = if step.offset_ms >= 0 {
+                previous_end + offset
+            } else {
+                previous_end.saturating_sub(offset).max(previous_start)
+            };
+
+            if !wait_until(&playback, started + start) {
+                return;
+            }
+
+            previous_start = start;
+            previous_end = start + play_step(&app_handle, &playback, step);
+        }
+    });
+
+    Ok(id)
+}
+
+/// The macro bound to `keybind`, if there is one
+pub fn find_by_keybind<'a>(
+    settings_file: &'a SettingsFile,
+    keybind: &Keybind,
+) -> Option<&'a SoundMacro> {
+    settings_file
+        .macros
+        .iter()
+        .find(|sound_macro| sound_macro.keybind.as_ref() == Some(keybind))
+}
+
+/// Creates a macro, or replaces the one with the same name.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_macro(
+    name: String,
+    keybind: String,
+    steps: Vec<MacroStep>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving macro {}", name);
+
+    let name = name.trim().to_owned();
+    if name.is_empty() {
+        return Err(SettingsError::InvalidMacroName);
+    }
+    if steps.iter().any(|step| !volume::is_valid(step.volume)) {
+        return Err(SettingsError::InvalidVolume);
+    }
+    if steps
+        .iter()
+        .any(|step| !(-MAX_OFFSET_MS..=MAX_OFFSET_MS).contains(&step.offset_ms))
+    {
+        return Err(SettingsError::InvalidMacroOffset);
+    }
+
+    let keybind = keybind::parse_optional(&keybind)?;
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    validate_keybind(&settings_state, &name, None, keybind.as_ref())?;
+
+    let sound_macro = SoundMacro {
+        name,
+        keybind,
+        steps,
+    };
+    match settings_state
+        .macros
+        .iter_mut()
+        .find(|existing_macro| existing_macro.name == sound_macro.name)
+    {
+        Some(existing_macro) => *existing_macro = sound_macro,
+        None => settings_state.macros.push(sound_macro),
+    }
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn delete_macro(
+    name: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Deleting macro {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state
+        .macros
+        .retain(|sound_macro| sound_macro.name != name);
+
+    write_settings_file(&settings_state)
+}
+
+/// Previews a macro like `play_sound` does a sound. The returned playback can be stopped with
+/// `stop_sound`.
+#[tauri::command(rename_all = "snake_case")]
+pub fn play_macro(
+    name: String,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<PlaybackId, AppError> {
+    let settings_state = state.settings_state.lock().unwrap();
+    let sound_macro = settings_state
+        .macros
+        .iter()
+        .find(|sound_macro| sound_macro.name == name)
+        .ok_or(SoundsError::MacroNotFound(name.clone()))?;
+
+    start(&app_handle, &settings_state, sound_macro)
+}
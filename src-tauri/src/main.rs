@@ -1,13 +1,29 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::sync::Mutex;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tauri::Manager;
 
+mod audio_engine;
+mod effects;
 mod errors;
+mod fade;
 mod files;
+mod groups;
+mod keybind;
 mod keyboard_listener;
+mod loudness;
+mod macros;
+mod master_bus;
+mod playback;
+mod profiles;
+mod queue;
 mod settings;
 mod sounds;
+mod tags;
+mod trim;
+mod volume;
 
 #[derive(Debug)]
 pub struct SettingsState {
@@ -16,41 +32,89 @@ pub struct SettingsState {
 
 impl Default for SettingsState {
     fn default() -> Self {
-        match files::get_settings() {
-            Ok(settings_file_path) => {
-                // The settings file exists, proceed to load it
-                println!("Settings file found at {:?}", settings_file_path);
-            }
-            Err(_) => {
-                // The settings file does not exist, create the folder and file
-                println!("Settings file not found, creating default settings file.");
-                files::create_sounds_folder().expect("Failed to create sounds folder");
-                files::create_settings_file().expect("Failed to create settings file");
-            }
+        let settings_file_path =
+            files::get_settings_file_path().expect("Failed to get settings file path");
+        // A file that exists but can't be loaded is left alone, so it can still be fixed by hand
+        if fs::metadata(&settings_file_path).map_or(true, |metadata| metadata.len() == 0) {
+            // The settings file does not exist, create the folder and file
+            println!("Settings file not found, creating default settings file.");
+            files::create_sounds_folder().expect("Failed to create sounds folder");
+            files::create_settings_file().expect("Failed to create settings file");
+        } else {
+            // The settings file exists, proceed to load it
+            println!("Settings file found at {:?}", settings_file_path);
         }
 
+        // TODO: Convert noise_settings to HashMap for faster lookups
         let settings_file = files::get_settings().expect("Unable to load settings file");
 
-        let input_device = settings_file.input_device;
-        let output_device = settings_file.output_device;
-        // TODO: Convert to HashMap for faster lookups
-        let noise_settings = settings_file.noise_settings;
+        Self {
+            settings_state: Mutex::new(settings_file),
+        }
+    }
+}
+
+pub struct AudioEngineState {
+    audio_engine: Mutex<Option<audio_engine::AudioEngine>>,
+    // Outlives engine restarts so the UI keeps getting finished events for dropped voices
+    playbacks: playback::PlaybackRegistry,
+    bus_controls: Arc<master_bus::BusControls>,
+}
+
+impl AudioEngineState {
+    fn new(app_handle: tauri::AppHandle, settings_file: &settings::SettingsFile) -> Self {
+        let (input_device, output_device) = settings_file.audio_devices();
+        let bus_controls = Arc::new(master_bus::BusControls::new(settings_file));
 
         Self {
-            settings_state: Mutex::new(settings::SettingsFile {
+            audio_engine: Mutex::new(Self::start_engine(
                 input_device,
                 output_device,
-                noise_settings,
-            }),
+                Arc::clone(&bus_controls),
+            )),
+            playbacks: playback::PlaybackRegistry::new(app_handle),
+            bus_controls,
         }
     }
+
+    fn start_engine(
+        input_device: String,
+        output_device: String,
+        bus_controls: Arc<master_bus::BusControls>,
+    ) -> Option<audio_engine::AudioEngine> {
+        match audio_engine::AudioEngine::start(input_device, output_device, bus_controls) {
+            Ok(audio_engine) => Some(audio_engine),
+            Err(error) => {
+                // Sounds can't play until the devices are fixed, but the rest of the app still works
+                eprintln!("Failed to start audio engine: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Reopens the engine's streams, e.g. after the user picks different audio devices.
+    pub fn restart(&self, input_device: String, output_device: String) {
+        let mut audio_engine = self.audio_engine.lock().unwrap();
+        // Close the old streams before opening new ones on the same devices
+        *audio_engine = None;
+        *audio_engine =
+            Self::start_engine(input_device, output_device, Arc::clone(&self.bus_controls));
+    }
 }
 
 fn main() {
     tauri::Builder::default()
         .manage(SettingsState::default())
+        .manage(loudness::LoudnessState::default())
+        .manage(queue::QueueState::default())
         .setup(|app| {
+            let settings_state = app.state::<SettingsState>();
             let app_handle = app.handle();
+            let engine_state = AudioEngineState::new(
+                app_handle.clone(),
+                &settings_state.settings_state.lock().unwrap(),
+            );
+            app.manage(engine_state);
 
             std::thread::spawn(move || {
                 keyboard_listener::run_listener(app_handle);
@@ -62,10 +126,56 @@ fn main() {
             files::open_sounds_folder,
             sounds::get_sound_duration,
             sounds::play_sound,
+            sounds::stop_sound,
+            sounds::stop_all_sounds,
+            sounds::list_playing,
             settings::save_setting,
+            settings::save_effects,
+            settings::save_trim,
+            settings::save_fades,
+            settings::save_play_mode,
+            settings::save_voice_limits,
+            settings::save_max_polyphony,
+            settings::save_stop_fade,
+            trim::detect_trim_points,
+            settings::set_voice_chat_eq,
+            settings::save_master_bus_settings,
+            master_bus::set_master_volume,
+            master_bus::set_master_muted,
             settings::get_settings_file,
             settings::load_audio_devices,
             settings::save_audio_devices,
+            settings::save_hotkey,
+            settings::find_keybind_conflicts,
+            settings::get_leader_key,
+            settings::set_leader_key,
+            profiles::create_profile,
+            profiles::rename_profile,
+            profiles::duplicate_profile,
+            profiles::delete_profile,
+            profiles::activate_profile,
+            profiles::save_profile_audio_devices,
+            groups::save_sound_group,
+            groups::delete_sound_group,
+            macros::save_macro,
+            macros::delete_macro,
+            macros::play_macro,
+            queue::get_queue,
+            queue::enqueue,
+            queue::remove_from_queue,
+            queue::clear_queue,
+            queue::play_queue,
+            queue::stop_queue,
+            queue::skip_track,
+            queue::previous_track,
+            queue::save_queue_settings,
+            tags::add_tag,
+            tags::remove_tag,
+            tags::list_tags,
+            tags::list_sounds_by_tag,
+            tags::set_active_tag,
+            loudness::get_loudness_analyses,
+            loudness::set_target_lufs,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
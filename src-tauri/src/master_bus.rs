@@ -0,0 +1,553 @@
+use rodio::dynamic_mixer::DynamicMixer;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager, State};
+
+use crate::effects::eq::{self, FilterBank};
+use crate::errors::SettingsError;
+use crate::settings::{write_settings_file, SettingsFile};
+use crate::{volume, AudioEngineState, SettingsState};
+
+/// How far ahead the limiter looks, so it can turn the gain down before a peak arrives
+const LOOKAHEAD_MS: f32 = 5.0;
+const LIMITER_RELEASE_MS: f32 = 100.0;
+/// Master volume changes are eased in over this long, so they don't click
+const GAIN_SMOOTHING_MS: f32 = 20.0;
+/// How much the volume hotkeys change the master volumes by, in percent
+const VOLUME_STEP: f32 = 10.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputBus {
+    /// What the user hears
+    User,
+    /// What the call hears
+    Listener,
+}
+
+/// Applied on top of every sound's own volume, including sounds that are already playing.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MasterVolume {
+    /// Percent, see `volume`
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for MasterVolume {
+    fn default() -> Self {
+        MasterVolume {
+            volume: 100.0,
+            muted: false,
+        }
+    }
+}
+
+impl MasterVolume {
+    fn gain(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            volume::to_gain(self.volume)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressorSettings {
+    pub threshold_db: f32,
+    /// Input dB over the threshold for each output dB over it
+    pub ratio: f32,
+    pub attack_ms: f32,
+    pub release_ms: f32,
+}
+
+/// Dynamics processing on everything the call hears, so stacked sounds don't clip.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MasterBusSettings {
+    /// The limiter never lets the listener bus go above this, in dBFS
+    pub ceiling_db: f32,
+    /// Evens out the mix before it reaches the limiter, when set
+    #[serde(default)]
+    pub compressor: Option<CompressorSettings>,
+}
+
+impl Default for MasterBusSettings {
+    fn default() -> Self {
+        MasterBusSettings {
+            ceiling_db: -1.0,
+            compressor: None,
+        }
+    }
+}
+
+impl MasterBusSettings {
+    pub fn is_valid(&self) -> bool {
+        (-20.0..=0.0).contains(&self.ceiling_db)
+            && self.compressor.as_ref().is_none_or(|compressor| {
+                (-60.0..=0.0).contains(&compressor.threshold_db)
+                    && (1.0..=20.0).contains(&compressor.ratio)
+                    && (0.1..=200.0).contains(&compressor.attack_ms)
+                    && (10.0..=2000.0).contains(&compressor.release_ms)
+            })
+    }
+}
+
+/// Settings that apply to a whole bus. Shared with the audio thread, so changing them
+/// affects sounds that are already playing.
+#[derive(Debug)]
+pub struct BusControls {
+    /// Runs the listener bus through the voice chat EQ
+    pub voice_chat_eq: AtomicBool,
+    master_bus: Mutex<MasterBusSettings>,
+    master_bus_changed: AtomicBool,
+    // Gains are stored as `f32` bits so the audio thread can read them without locking
+    user_gain: AtomicU32,
+    listener_gain: AtomicU32,
+}
+
+impl BusControls {
+    pub fn new(settings_file: &SettingsFile) -> Self {
+        BusControls {
+            voice_chat_eq: AtomicBool::new(settings_file.voice_chat_eq),
+            master_bus: Mutex::new(settings_file.master_bus.clone()),
+            master_bus_changed: AtomicBool::new(false),
+            user_gain: AtomicU32::new(settings_file.user_master_volume.gain().to_bits()),
+            listener_gain: AtomicU32::new(settings_file.listener_master_volume.gain().to_bits()),
+        }
+    }
+
+    fn master_gain(&self, bus: OutputBus) -> &AtomicU32 {
+        match bus {
+            OutputBus::User => &self.user_gain,
+            OutputBus::Listener => &self.listener_gain,
+        }
+    }
+
+    pub fn set_master_volume(&self, bus: OutputBus, master_volume: &MasterVolume) {
+        self.master_gain(bus)
+            .store(master_volume.gain().to_bits(), Ordering::Relaxed);
+    }
+
+    fn gain(&self, bus: OutputBus) -> f32 {
+        f32::from_bits(self.master_gain(bus).load(Ordering::Relaxed))
+    }
+
+    pub fn set_master_bus(&self, settings: MasterBusSettings) {
+        *self.master_bus.lock().unwrap() = settings;
+        self.master_bus_changed.store(true, Ordering::Release);
+    }
+}
+
+/// Converts a time constant to the coefficient of a one-pole smoother.
+fn smoothing_coefficient(ms: f32, sample_rate: u32) -> f32 {
+    (-1.0 / (ms.max(0.01) / 1000.0 * sample_rate as f32)).exp()
+}
+
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Eases towards the master gain instead of jumping to it.
+struct SmoothedGain {
+    current: f32,
+    coefficient: f32,
+}
+
+impl SmoothedGain {
+    /// `rate` is how often `next` is called per second
+    fn new(gain: f32, rate: u32) -> Self {
+        SmoothedGain {
+            current: gain,
+            coefficient: smoothing_coefficient(GAIN_SMOOTHING_MS, rate),
+        }
+    }
+
+    #[inline]
+    fn next(&mut self, target: f32) -> f32 {
+        self.current = target + (self.current - target) * self.coefficient;
+        self.current
+    }
+}
+
+struct Compressor {
+    threshold_db: f32,
+    slope: f32,
+    attack: f32,
+    release: f32,
+    /// Smoothed level of the input, in dB
+    envelope_db: f32,
+}
+
+impl Compressor {
+    fn new(settings: &CompressorSettings, sample_rate: u32) -> Self {
+        Compressor {
+            threshold_db: settings.threshold_db,
+            slope: 1.0 - 1.0 / settings.ratio.max(1.0),
+            attack: smoothing_coefficient(settings.attack_ms, sample_rate),
+            release: smoothing_coefficient(settings.release_ms, sample_rate),
+            envelope_db: -120.0,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        let level_db = 20.0 * peak.max(1e-6).log10();
+
+        let coefficient = if level_db > self.envelope_db {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope_db = level_db + (self.envelope_db - level_db) * coefficient;
+
+        let reduction_db = (self.envelope_db - self.threshold_db).max(0.0) * self.slope;
+        let gain = db_to_gain(-reduction_db);
+        frame.iter_mut().for_each(|sample| *sample *= gain);
+    }
+}
+
+/// Delays the signal by the look-ahead time, turning the gain down early enough that
+/// peaks come out under the ceiling.
+struct Limiter {
+    ceiling: f32,
+    release: f32,
+    attack: f32,
+    gain: f32,
+    /// Interleaved frames waiting to come out
+    delay: VecDeque<f32>,
+    /// Gain each delayed frame needs, kept increasing from front to back so the front is
+    /// always the lowest gain needed within the look-ahead
+    required_gains: VecDeque<(usize, f32)>,
+    lookahead_frames: usize,
+    frame_index: usize,
+}
+
+impl Limiter {
+    fn new(ceiling_db: f32, channels: usize, sample_rate: u32) -> Self {
+        let lookahead_frames = ((LOOKAHEAD_MS / 1000.0 * sample_rate as f32) as usize).max(1);
+
+        Limiter {
+            ceiling: db_to_gain(ceiling_db),
+            release: smoothing_coefficient(LIMITER_RELEASE_MS, sample_rate),
+            // Reaches the gain it needs well within the look-ahead
+            attack: smoothing_coefficient(LOOKAHEAD_MS / 5.0, sample_rate),
+            gain: 1.0,
+            delay: VecDeque::from(vec![0.0; lookahead_frames * channels]),
+            required_gains: VecDeque::new(),
+            lookahead_frames,
+            frame_index: 0,
+        }
+    }
+
+    fn process(&mut self, frame: &mut [f32]) {
+        let peak = frame
+            .iter()
+            .fold(0f32, |peak, sample| peak.max(sample.abs()));
+        let required_gain = if peak > self.ceiling {
+            self.ceiling / peak
+        } else {
+            1.0
+        };
+
+        // Sliding minimum over the look-ahead window
+        while self
+            .required_gains
+            .back()
+            .is_some_and(|(_, gain)| *gain >= required_gain)
+        {
+            self.required_gains.pop_back();
+        }
+        self.required_gains
+            .push_back((self.frame_index, required_gain));
+        while self
+            .required_gains
+            .front()
+            .is_some_and(|(index, _)| *index + self.lookahead_frames < self.frame_index)
+        {
+            self.required_gains.pop_front();
+        }
+        self.frame_index += 1;
+
+        let target = self.required_gains.front().map_or(1.0, |(_, gain)| *gain);
+        let coefficient = if target < self.gain {
+            self.attack
+        } else {
+            self.release
+        };
+        self.gain = target + (self.gain - target) * coefficient;
+
+        self.delay.extend(frame.iter().copied());
+        for sample in frame.iter_mut() {
+            let delayed = self.delay.pop_front().unwrap_or(0.0);
+            // Anything the envelope didn't catch in time is clipped, the ceiling always holds
+            *sample = (delayed * self.gain).clamp(-self.ceiling, self.ceiling);
+        }
+    }
+}
+
+/// Processing applied to the mix the listener bus sends to the call.
+pub struct ListenerOutput {
+    mixer: DynamicMixer<f32>,
+    controls: Arc<BusControls>,
+    voice_chat_eq: FilterBank,
+    gain: SmoothedGain,
+    compressor: Option<Compressor>,
+    limiter: Limiter,
+    /// The frame being played
+    frame: Vec<f32>,
+    position: usize,
+}
+
+impl ListenerOutput {
+    pub fn new(mixer: DynamicMixer<f32>, controls: Arc<BusControls>) -> Self {
+        let channels = mixer.channels();
+        let voice_chat_eq = FilterBank::new(eq::voice_chat_preset(mixer.sample_rate()), channels);
+        let settings = controls.master_bus.lock().unwrap().clone();
+
+        let mut listener_output = ListenerOutput {
+            gain: SmoothedGain::new(controls.gain(OutputBus::Listener), mixer.sample_rate()),
+            compressor: None,
+            limiter: Limiter::new(settings.ceiling_db, channels as usize, mixer.sample_rate()),
+            mixer,
+            controls,
+            voice_chat_eq,
+            frame: vec![0.0; channels.max(1) as usize],
+            position: channels.max(1) as usize,
+        };
+        listener_output.apply_settings(&settings);
+
+        listener_output
+    }
+
+    fn apply_settings(&mut self, settings: &MasterBusSettings) {
+        let sample_rate = self.mixer.sample_rate();
+
+        self.compressor = settings
+            .compressor
+            .as_ref()
+            .map(|compressor| Compressor::new(compressor, sample_rate));
+        self.limiter.ceiling = db_to_gain(settings.ceiling_db);
+    }
+
+    /// Reads and processes the next frame of the mix, returning false once it has ended.
+    fn next_frame(&mut self) -> bool {
+        // Never blocks the audio thread, a busy lock is tried again on the next frame
+        if self.controls.master_bus_changed.load(Ordering::Acquire) {
+            let settings = self.controls.master_bus.try_lock().ok().map(|settings| {
+                // Cleared while the lock is held, so a change made right after isn't missed
+                self.controls
+                    .master_bus_changed
+                    .store(false, Ordering::Release);
+                settings.clone()
+            });
+            if let Some(settings) = settings {
+                self.apply_settings(&settings);
+            }
+        }
+
+        let voice_chat_eq = self.controls.voice_chat_eq.load(Ordering::Relaxed);
+        for sample in self.frame.iter_mut() {
+            let Some(mixed) = self.mixer.next() else {
+                return false;
+            };
+            *sample = if voice_chat_eq {
+                self.voice_chat_eq.process(mixed)
+            } else {
+                mixed
+            };
+        }
+
+        // Before the limiter, so turning the master volume up can't make it clip either
+        let gain = self.gain.next(self.controls.gain(OutputBus::Listener));
+        self.frame.iter_mut().for_each(|sample| *sample *= gain);
+
+        if let Some(compressor) = self.compressor.as_mut() {
+            compressor.process(&mut self.frame);
+        }
+        self.limiter.process(&mut self.frame);
+
+        true
+    }
+}
+
+impl Iterator for ListenerOutput {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.position == self.frame.len() {
+            if !self.next_frame() {
+                return None;
+            }
+            self.position = 0;
+        }
+
+        let sample = self.frame[self.position];
+        self.position += 1;
+
+        Some(sample)
+    }
+}
+
+impl Source for ListenerOutput {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.mixer.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.mixer.total_duration()
+    }
+}
+
+/// Applies the user's master volume to the mix they hear.
+pub struct UserOutput {
+    mixer: DynamicMixer<f32>,
+    controls: Arc<BusControls>,
+    gain: SmoothedGain,
+}
+
+impl UserOutput {
+    pub fn new(mixer: DynamicMixer<f32>, controls: Arc<BusControls>) -> Self {
+        let samples_per_second = mixer.sample_rate() * mixer.channels().max(1) as u32;
+
+        UserOutput {
+            gain: SmoothedGain::new(controls.gain(OutputBus::User), samples_per_second),
+            mixer,
+            controls,
+        }
+    }
+}
+
+impl Iterator for UserOutput {
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.mixer.next()?;
+        Some(sample * self.gain.next(self.controls.gain(OutputBus::User)))
+    }
+}
+
+impl Source for UserOutput {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.mixer.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.mixer.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.mixer.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.mixer.total_duration()
+    }
+}
+
+/// Changes a bus's master volume, applying it to what's playing right away and saving it.
+pub fn update_master_volume<F>(
+    app_handle: &AppHandle,
+    settings_file: &mut SettingsFile,
+    bus: OutputBus,
+    update: F,
+) -> Result<(), SettingsError>
+where
+    F: FnOnce(&mut MasterVolume),
+{
+    let master_volume = settings_file.master_volume_mut(bus);
+    update(master_volume);
+    master_volume.volume = master_volume.volume.clamp(0.0, volume::MAX_PERCENT);
+
+    app_handle
+        .state::<AudioEngineState>()
+        .bus_controls
+        .set_master_volume(bus, master_volume);
+    let _ = app_handle.emit_all("master-volume-changed", (bus, master_volume.clone()));
+
+    write_settings_file(settings_file)
+}
+
+/// Turns both master volumes up or down by one step, for the volume hotkeys.
+pub fn step_master_volumes(
+    app_handle: &AppHandle,
+    settings_file: &mut SettingsFile,
+    steps: f32,
+) -> Result<(), SettingsError> {
+    for bus in [OutputBus::User, OutputBus::Listener] {
+        update_master_volume(app_handle, settings_file, bus, |master_volume| {
+            master_volume.volume += steps * VOLUME_STEP;
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Mutes both buses, or unmutes them if both are already muted, for the mute hotkey.
+pub fn toggle_master_mute(
+    app_handle: &AppHandle,
+    settings_file: &mut SettingsFile,
+) -> Result<(), SettingsError> {
+    let muted =
+        !(settings_file.user_master_volume.muted && settings_file.listener_master_volume.muted);
+
+    for bus in [OutputBus::User, OutputBus::Listener] {
+        update_master_volume(app_handle, settings_file, bus, |master_volume| {
+            master_volume.muted = muted;
+        })?;
+    }
+
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_master_volume(
+    bus: OutputBus,
+    volume: f32,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Setting {:?} master volume to {}", bus, volume);
+
+    if !volume::is_valid(volume) {
+        return Err(SettingsError::InvalidVolume);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    update_master_volume(&app_handle, &mut settings_state, bus, |master_volume| {
+        master_volume.volume = volume;
+    })
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_master_muted(
+    bus: OutputBus,
+    muted: bool,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Setting {:?} master mute to {}", bus, muted);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    update_master_volume(&app_handle, &mut settings_state, bus, |master_volume| {
+        master_volume.muted = muted;
+    })
+}
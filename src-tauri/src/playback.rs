@@ -0,0 +1,389 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::fade;
+
+pub type PlaybackId = u64;
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackInfo {
+    pub id: PlaybackId,
+    pub filename: String,
+}
+
+/// What triggering a sound does when it's already playing
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum PlayMode {
+    /// Plays another copy on top
+    #[default]
+    Overlap,
+    /// Stops the sound and plays it from the start
+    Restart,
+    /// Stops the sound instead of playing it
+    Toggle,
+    /// Plays only while the keybind is held down
+    Hold,
+    /// Repeats the sound until it's stopped, triggering it again stops it like `Toggle`
+    Loop,
+}
+
+/// What started a playback, which decides what else can stop it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlaybackKind {
+    /// A sound or macro, subject to choke groups, polyphony limits and play modes
+    Sound,
+    /// A track of the queue, which only the queue itself and stopping everything stop
+    Queue,
+}
+
+/// State shared between every voice of a single playback.
+struct PlaybackControls {
+    id: PlaybackId,
+    stopped: AtomicBool,
+    /// How long voices take to fade out once stopped
+    stop_fade: Duration,
+    voices_left: AtomicUsize,
+}
+
+impl PlaybackControls {
+    fn finish_voice(&self, finished_tx: &mpsc::Sender<PlaybackId>) {
+        // The last voice to finish is the one that finishes the playback
+        if self.voices_left.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let _ = finished_tx.send(self.id);
+        }
+    }
+}
+
+struct Playback {
+    info: PlaybackInfo,
+    kind: PlaybackKind,
+    choke_group: Option<String>,
+    controls: Arc<PlaybackControls>,
+}
+
+impl Playback {
+    fn is_sound(&self) -> bool {
+        self.kind == PlaybackKind::Sound
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.controls.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Returns false if it was already stopped.
+    fn stop(&self) -> bool {
+        !self.controls.stopped.swap(true, Ordering::Relaxed)
+    }
+}
+
+/// Keeps track of every sound that is currently playing, so it can be listed and stopped.
+pub struct PlaybackRegistry {
+    playbacks: Arc<Mutex<HashMap<PlaybackId, Playback>>>,
+    next_id: AtomicU64,
+    finished_tx: Mutex<mpsc::Sender<PlaybackId>>,
+    app_handle: AppHandle,
+}
+
+impl PlaybackRegistry {
+    pub fn new(app_handle: AppHandle) -> Self {
+        let playbacks: Arc<Mutex<HashMap<PlaybackId, Playback>>> = Arc::default();
+        let (finished_tx, finished_rx) = mpsc::channel::<PlaybackId>();
+
+        // Voices report back from the audio thread, so the bookkeeping happens here instead
+        let finished_playbacks = Arc::clone(&playbacks);
+        let finished_app_handle = app_handle.clone();
+        std::thread::spawn(move || {
+            for id in finished_rx {
+                let playback = finished_playbacks.lock().unwrap().remove(&id);
+                if let Some(playback) = playback {
+                    let _ = finished_app_handle.emit_all("playback-finished", playback.info);
+                }
+            }
+        });
+
+        PlaybackRegistry {
+            playbacks,
+            next_id: AtomicU64::new(0),
+            finished_tx: Mutex::new(finished_tx),
+            app_handle,
+        }
+    }
+
+    /// Registers a new playback. Every voice created from the returned handle has to be
+    /// handed to the engine, the playback only finishes once all of them have.
+    pub fn register(
+        &self,
+        filename: String,
+        kind: PlaybackKind,
+        choke_group: Option<String>,
+        stop_fade: Duration,
+    ) -> NewPlayback {
+        let info = PlaybackInfo {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            filename,
+        };
+        let controls = Arc::new(PlaybackControls {
+            id: info.id,
+            stopped: AtomicBool::new(false),
+            stop_fade,
+            voices_left: AtomicUsize::new(0),
+        });
+
+        self.playbacks.lock().unwrap().insert(
+            info.id,
+            Playback {
+                info: info.clone(),
+                kind,
+                choke_group,
+                controls: Arc::clone(&controls),
+            },
+        );
+        let _ = self.app_handle.emit_all("playback-started", info.clone());
+
+        NewPlayback {
+            info,
+            controls,
+            finished_tx: self.finished_tx.lock().unwrap().clone(),
+        }
+    }
+
+    /// Fades the playback out and then stops it. Returns false if no playback with this ID
+    /// is playing.
+    pub fn stop(&self, id: PlaybackId) -> bool {
+        match self.playbacks.lock().unwrap().get(&id) {
+            Some(playback) => {
+                playback.stop();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops every playback of a sound that isn't already stopping, leaving the queue alone.
+    /// Returns false if there were none.
+    pub fn stop_file(&self, filename: &str) -> bool {
+        let mut stopped_any = false;
+        for playback in self.playbacks.lock().unwrap().values() {
+            if playback.is_sound() && playback.info.filename == filename && playback.stop() {
+                stopped_any = true;
+            }
+        }
+
+        stopped_any
+    }
+
+    /// Stops every sound in the choke group.
+    pub fn choke(&self, choke_group: &str) {
+        for playback in self.playbacks.lock().unwrap().values() {
+            if playback.is_sound() && playback.choke_group.as_deref() == Some(choke_group) {
+                playback.stop();
+            }
+        }
+    }
+
+    /// Stops the oldest sounds until one more of `filename` fits under both the sound's and
+    /// the overall polyphony limits. Queue tracks neither count nor get stopped.
+    pub fn make_room(&self, filename: &str, max_per_sound: Option<u32>, max_total: Option<u32>) {
+        let playbacks = self.playbacks.lock().unwrap();
+        let mut oldest_first: Vec<&Playback> = playbacks
+            .values()
+            .filter(|playback| playback.is_sound())
+            .collect();
+        oldest_first.sort_by_key(|playback| playback.info.id);
+
+        if let Some(max_per_sound) = max_per_sound {
+            let same_sound = oldest_first
+                .iter()
+                .copied()
+                .filter(|playback| playback.info.filename == filename);
+            steal_oldest(same_sound, max_per_sound);
+        }
+        if let Some(max_total) = max_total {
+            steal_oldest(oldest_first.iter().copied(), max_total);
+        }
+    }
+
+    pub fn stop_all(&self) {
+        for playback in self.playbacks.lock().unwrap().values() {
+            playback.stop();
+        }
+    }
+
+    pub fn list(&self) -> Vec<PlaybackInfo> {
+        let mut playing: Vec<PlaybackInfo> = self
+            .playbacks
+            .lock()
+            .unwrap()
+            .values()
+            .map(|playback| playback.info.clone())
+            .collect();
+        playing.sort_by_key(|info| info.id);
+
+        playing
+    }
+}
+
+/// Stops the oldest of `oldest_first` still playing, until one more would fit under `max`.
+fn steal_oldest<'a>(oldest_first: impl Iterator<Item = &'a Playback>, max: u32) {
+    let playing: Vec<&Playback> = oldest_first
+        .filter(|playback| !playback.is_stopped())
+        .collect();
+    let excess = (playing.len() + 1).saturating_sub(max as usize);
+
+    for playback in playing.into_iter().take(excess) {
+        playback.stop();
+    }
+}
+
+pub struct NewPlayback {
+    pub info: PlaybackInfo,
+    controls: Arc<PlaybackControls>,
+    finished_tx: mpsc::Sender<PlaybackId>,
+}
+
+impl NewPlayback {
+    pub fn voice<S>(&self, source: S) -> Voice<S>
+    where
+        S: Source<Item = f32>,
+    {
+        self.controls.voices_left.fetch_add(1, Ordering::Relaxed);
+
+        Voice {
+            source,
+            controls: Arc::clone(&self.controls),
+            finished_tx: self.finished_tx.clone(),
+            finished: false,
+            stop_fade: None,
+        }
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.controls.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Keeps the playback from finishing until the returned hold is dropped, for playbacks
+    /// that add voices over time.
+    pub fn hold(&self) -> PlaybackHold {
+        self.controls.voices_left.fetch_add(1, Ordering::Relaxed);
+
+        PlaybackHold {
+            controls: Arc::clone(&self.controls),
+            finished_tx: self.finished_tx.clone(),
+        }
+    }
+}
+
+pub struct PlaybackHold {
+    controls: Arc<PlaybackControls>,
+    finished_tx: mpsc::Sender<PlaybackId>,
+}
+
+impl Drop for PlaybackHold {
+    fn drop(&mut self) {
+        self.controls.finish_voice(&self.finished_tx);
+    }
+}
+
+/// Samples left of a fade out, and how many it started with
+struct StopFade {
+    left: usize,
+    len: usize,
+}
+
+/// Wraps a source so its playback can be stopped, and reports back once it's done.
+pub struct Voice<S> {
+    source: S,
+    controls: Arc<PlaybackControls>,
+    finished_tx: mpsc::Sender<PlaybackId>,
+    finished: bool,
+    /// Set once the playback is stopped, so the voice fades out instead of cutting off
+    stop_fade: Option<StopFade>,
+}
+
+impl<S> Voice<S> {
+    fn finish(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+
+        self.controls.finish_voice(&self.finished_tx);
+    }
+}
+
+impl<S> Iterator for Voice<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        if self.finished {
+            return None;
+        }
+        if self.stop_fade.is_none() && self.controls.stopped.load(Ordering::Relaxed) {
+            let len = fade::samples(
+                self.controls.stop_fade,
+                self.source.sample_rate(),
+                self.source.channels(),
+            );
+            self.stop_fade = Some(StopFade { left: len, len });
+        }
+
+        let gain = match self.stop_fade.as_mut() {
+            Some(stop_fade) if stop_fade.left == 0 => {
+                self.finish();
+                return None;
+            }
+            Some(stop_fade) => {
+                stop_fade.left -= 1;
+                (stop_fade.left + 1) as f32 / stop_fade.len as f32
+            }
+            None => 1.0,
+        };
+
+        match self.source.next() {
+            Some(sample) => Some(sample * gain),
+            None => {
+                self.finish();
+                None
+            }
+        }
+    }
+}
+
+impl<S> Source for Voice<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+impl<S> Drop for Voice<S> {
+    // Voices are dropped without finishing when the engine's streams are closed
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
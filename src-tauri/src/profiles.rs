@@ -0,0 +1,229 @@
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+
+use crate::errors::SettingsError;
+use crate::settings::{write_settings_file, KeybindSetting, SettingsFile};
+use crate::{AudioEngineState, SettingsState};
+
+pub const DEFAULT_PROFILE: &str = "Default";
+
+/// A soundboard of its own, so the same keybind can play different sounds per game.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Profile {
+    pub name: String,
+    pub noise_settings: Vec<KeybindSetting>,
+    /// Overrides the global devices while this profile is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_device: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_device: Option<String>,
+    /// Only sounds with this tag respond to their keybinds, when set
+    #[serde(default)]
+    pub active_tag: Option<String>,
+}
+
+impl Profile {
+    pub fn new(name: String) -> Self {
+        Profile {
+            name,
+            noise_settings: Vec::new(),
+            input_device: None,
+            output_device: None,
+            active_tag: None,
+        }
+    }
+
+    /// The sounds whose keybinds are live, taking the active tag into account
+    pub fn active_noise_settings(&self) -> impl Iterator<Item = &KeybindSetting> {
+        self.noise_settings.iter().filter(move |setting| {
+            self.active_tag
+                .as_ref()
+                .is_none_or(|tag| setting.tags.contains(tag))
+        })
+    }
+}
+
+fn find_profile(settings_file: &SettingsFile, name: &str) -> Result<usize, SettingsError> {
+    settings_file
+        .find_profile(name)
+        .ok_or_else(|| SettingsError::ProfileNotFound(name.to_owned()))
+}
+
+fn validate_new_name(settings_file: &SettingsFile, name: &str) -> Result<String, SettingsError> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(SettingsError::InvalidProfileName);
+    }
+    if settings_file.find_profile(name).is_some() {
+        return Err(SettingsError::ProfileAlreadyExists(name.to_owned()));
+    }
+
+    Ok(name.to_owned())
+}
+
+fn restart_engine(app_handle: &AppHandle, settings_file: &SettingsFile) {
+    let (input_device, output_device) = settings_file.audio_devices();
+    app_handle
+        .state::<AudioEngineState>()
+        .restart(input_device, output_device);
+}
+
+/// Makes `name` the active profile, moving the audio engine over if the profile uses
+/// different devices.
+pub fn activate(
+    app_handle: &AppHandle,
+    settings_file: &mut SettingsFile,
+    name: &str,
+) -> Result<(), SettingsError> {
+    let index = find_profile(settings_file, name)?;
+    let previous_devices = settings_file.audio_devices();
+    settings_file.active_profile = settings_file.profiles[index].name.clone();
+
+    if settings_file.audio_devices() != previous_devices {
+        restart_engine(app_handle, settings_file);
+    }
+
+    write_settings_file(settings_file)?;
+    let _ = app_handle.emit_all("profile-changed", settings_file.active_profile.clone());
+
+    Ok(())
+}
+
+/// Activates the profile after the active one, wrapping around to the first.
+pub fn activate_next(
+    app_handle: &AppHandle,
+    settings_file: &mut SettingsFile,
+) -> Result<(), SettingsError> {
+    let index = settings_file
+        .find_profile(&settings_file.active_profile)
+        .unwrap_or(0);
+    let next_profile = settings_file.profiles[(index + 1) % settings_file.profiles.len()]
+        .name
+        .clone();
+
+    activate(app_handle, settings_file, &next_profile)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn create_profile(
+    name: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Creating profile {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let name = validate_new_name(&settings_state, &name)?;
+
+    // Start with every sound unbound
+    let mut profile = Profile::new(name);
+    profile.noise_settings = settings_state
+        .active_profile()
+        .noise_settings
+        .iter()
+        .map(|setting| KeybindSetting::new(setting.filename.clone()))
+        .collect();
+    settings_state.profiles.push(profile);
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn rename_profile(
+    name: String,
+    new_name: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Renaming profile {} to {}", name, new_name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let index = find_profile(&settings_state, &name)?;
+    let new_name = validate_new_name(&settings_state, &new_name)?;
+
+    if settings_state.active_profile == name {
+        settings_state.active_profile = new_name.clone();
+    }
+    settings_state.profiles[index].name = new_name;
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn duplicate_profile(
+    name: String,
+    new_name: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Duplicating profile {} as {}", name, new_name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let index = find_profile(&settings_state, &name)?;
+    let new_name = validate_new_name(&settings_state, &new_name)?;
+
+    let mut profile = settings_state.profiles[index].clone();
+    profile.name = new_name;
+    settings_state.profiles.push(profile);
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn delete_profile(
+    name: String,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Deleting profile {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let index = find_profile(&settings_state, &name)?;
+
+    if settings_state.profiles.len() == 1 {
+        return Err(SettingsError::DeleteLastProfile);
+    }
+
+    if settings_state.active_profile == name {
+        activate_next(&app_handle, &mut settings_state)?;
+    }
+    settings_state.profiles.remove(index);
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn activate_profile(
+    name: String,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Activating profile {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    activate(&app_handle, &mut settings_state, &name)
+}
+
+/// Gives a profile its own devices, or makes it use the global ones again when `None`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_profile_audio_devices(
+    name: String,
+    input_device: Option<String>,
+    output_device: Option<String>,
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Saving audio devices for profile {}", name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let index = find_profile(&settings_state, &name)?;
+
+    let profile = &mut settings_state.profiles[index];
+    profile.input_device = input_device;
+    profile.output_device = output_device;
+
+    if settings_state.active_profile == name {
+        restart_engine(&app_handle, &settings_state);
+    }
+
+    write_settings_file(&settings_state)
+}
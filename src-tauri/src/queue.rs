@@ -0,0 +1,551 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, State};
+
+use crate::errors::{AppError, SettingsError, SoundsError};
+use crate::fade::{self, FadeOut};
+use crate::playback::{NewPlayback, PlayMode, PlaybackId, PlaybackKind};
+use crate::settings::{write_settings_file, KeybindSetting, SettingsFile};
+use crate::{sounds, AudioEngineState, SettingsState};
+
+/// How often the thread waiting on the current track checks whether it was stopped
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// Shortest time between the starts of two tracks, so very short ones can't make the queue spin
+const MIN_TRACK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum RepeatMode {
+    /// Stops after the last track
+    #[default]
+    Off,
+    /// Starts over from the first track after the last one
+    All,
+    /// Plays the same track until it's skipped
+    One,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueSettings {
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    /// How long one track fades into the next, 0 plays them back to back
+    pub crossfade_ms: u64,
+}
+
+#[derive(Default)]
+struct Queue {
+    /// Filenames in the order they were enqueued
+    tracks: Vec<String>,
+    /// Indices into `tracks` in the order they play, shuffled when shuffle is on
+    order: Vec<usize>,
+    /// Index into `order` of the current track
+    position: Option<usize>,
+    playing: Option<PlaybackId>,
+    /// Bumped whenever a track is started or the queue is stopped, so threads waiting on tracks
+    /// that are no longer current know to give up
+    generation: u64,
+}
+
+impl Queue {
+    fn reorder(&mut self, shuffle: bool) {
+        let current = self.current_track();
+        self.order = (0..self.tracks.len()).collect();
+
+        if shuffle {
+            fastrand::shuffle(&mut self.order);
+            // The track that's playing stays first, so shuffling doesn't interrupt it
+            if let Some(current) = current {
+                let index = self
+                    .order
+                    .iter()
+                    .position(|track| *track == current)
+                    .unwrap();
+                self.order.swap(0, index);
+            }
+        }
+
+        self.position =
+            current.and_then(|current| self.order.iter().position(|track| *track == current));
+    }
+
+    fn current_track(&self) -> Option<usize> {
+        self.position.map(|position| self.order[position])
+    }
+
+    /// Where to go after the current track. `skipped` is false when it ended on its own.
+    fn next_position(&self, repeat: RepeatMode, skipped: bool) -> Option<usize> {
+        let position = match self.position {
+            Some(position) => position,
+            None => return (!self.order.is_empty()).then_some(0),
+        };
+
+        if repeat == RepeatMode::One && !skipped {
+            Some(position)
+        } else if position + 1 < self.order.len() {
+            Some(position + 1)
+        } else if repeat != RepeatMode::Off {
+            Some(0)
+        } else {
+            None
+        }
+    }
+
+    fn previous_position(&self, repeat: RepeatMode) -> Option<usize> {
+        match self.position? {
+            0 if repeat != RepeatMode::Off => self.order.len().checked_sub(1),
+            0 => Some(0),
+            position => Some(position - 1),
+        }
+    }
+
+    fn status(&self) -> QueueStatus {
+        QueueStatus {
+            tracks: self
+                .order
+                .iter()
+                .map(|track| self.tracks[*track].clone())
+                .collect(),
+            position: self.position,
+            playing: self.playing.is_some(),
+        }
+    }
+}
+
+/// The queue as the frontend sees it
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueStatus {
+    /// In the order they play
+    pub tracks: Vec<String>,
+    /// Index into `tracks` of the current track
+    pub position: Option<usize>,
+    pub playing: bool,
+}
+
+/// Background music, played back to back on the listener bus alongside sounds.
+#[derive(Default)]
+pub struct QueueState {
+    queue: Mutex<Queue>,
+}
+
+/// Lets the queue know once its track is `crossfade` away from the end, or has run out
+/// sooner than that.
+struct TrackEnding<S> {
+    source: S,
+    samples_left: usize,
+    ending_tx: Option<mpsc::Sender<()>>,
+}
+
+impl<S> TrackEnding<S>
+where
+    S: Source<Item = f32>,
+{
+    fn new(
+        source: S,
+        duration: Duration,
+        crossfade: Duration,
+        ending_tx: mpsc::Sender<()>,
+    ) -> Self {
+        let (sample_rate, channels) = (source.sample_rate(), source.channels());
+
+        TrackEnding {
+            samples_left: fade::samples(duration.saturating_sub(crossfade), sample_rate, channels),
+            source,
+            ending_tx: Some(ending_tx),
+        }
+    }
+}
+
+impl<S> Iterator for TrackEnding<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    #[inline]
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.source.next();
+
+        if sample.is_none() || self.samples_left == 0 {
+            if let Some(ending_tx) = self.ending_tx.take() {
+                let _ = ending_tx.send(());
+            }
+        } else {
+            self.samples_left -= 1;
+        }
+
+        sample
+    }
+}
+
+impl<S> Source for TrackEnding<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+fn emit_changed(app_handle: &AppHandle, queue: &Queue) {
+    let _ = app_handle.emit_all("queue-changed", queue.status());
+}
+
+/// The active profile's setting for a track, so its effects and listener volume apply
+fn track_setting(settings_file: &SettingsFile, filename: &str) -> KeybindSetting {
+    let mut setting = settings_file.setting_or_default(filename);
+    // The queue decides what plays next
+    setting.play_mode = PlayMode::Overlap;
+
+    setting
+}
+
+fn stop_current(app_handle: &AppHandle, queue: &mut Queue) {
+    queue.generation += 1;
+    if let Some(id) = queue.playing.take() {
+        app_handle.state::<AudioEngineState>().playbacks.stop(id);
+    }
+}
+
+/// Stops whatever is playing and plays the track at `position`, or stops the queue if
+/// there isn't one. Tracks that can't be played are skipped.
+fn play_position(
+    app_handle: &AppHandle,
+    settings_file: &SettingsFile,
+    queue: &mut Queue,
+    position: Option<usize>,
+) -> Result<(), AppError> {
+    let fade_in = queue.playing.is_some();
+    stop_current(app_handle, queue);
+
+    let mut position = position.filter(|position| *position < queue.order.len());
+    // Every track gets one try, so a queue that can't play anything stops instead of going round
+    let mut tries_left = queue.order.len();
+    while let Some(current) = position {
+        queue.position = Some(current);
+        match play_track(app_handle, settings_file, queue, current, fade_in) {
+            Ok(()) => break,
+            Err(error) if tries_left > 1 => {
+                let filename = &queue.tracks[queue.order[current]];
+                eprintln!("Skipping {}: {}", filename, error);
+                tries_left -= 1;
+                position = queue.next_position(settings_file.queue.repeat, true);
+            }
+            Err(error) => {
+                emit_changed(app_handle, queue);
+                return Err(error);
+            }
+        }
+    }
+
+    if position.is_none() {
+        queue.position = None;
+    }
+    emit_changed(app_handle, queue);
+    Ok(())
+}
+
+/// Plays the track at `position`, fading it in over the crossfade if `fade_in`.
+fn play_track(
+    app_handle: &AppHandle,
+    settings_file: &SettingsFile,
+    queue: &mut Queue,
+    position: usize,
+    fade_in: bool,
+) -> Result<(), AppError> {
+    let setting = track_setting(settings_file, &queue.tracks[queue.order[position]]);
+    let duration = sounds::sound_duration(&setting)?;
+    if duration.is_zero() {
+        return Err(SoundsError::EmptyTrack.into());
+    }
+    // Short clips fade for half their length at most, so they're still heard
+    let crossfade = Duration::from_millis(settings_file.queue.crossfade_ms).min(duration / 2);
+
+    let mut voice = sounds::build_listener_voice(app_handle, settings_file.target_lufs, &setting)?;
+    if !crossfade.is_zero() {
+        if fade_in {
+            voice = Box::new(voice.fade_in(crossfade));
+        }
+        voice = Box::new(FadeOut::new(voice, duration, crossfade));
+    }
+    let (ending_tx, ending_rx) = mpsc::channel();
+    let voice = TrackEnding::new(voice, duration, crossfade, ending_tx);
+
+    let engine_state = app_handle.state::<AudioEngineState>();
+    let audio_engine = engine_state.audio_engine.lock().unwrap();
+    let audio_engine = audio_engine
+        .as_ref()
+        .ok_or(SoundsError::EngineUnavailable)?;
+
+    // Skipping fades the track out over the crossfade too, but never cuts it off with a click
+    let stop_fade = crossfade.max(Duration::from_millis(settings_file.stop_fade_ms));
+    let playback = engine_state.playbacks.register(
+        setting.filename.clone(),
+        PlaybackKind::Queue,
+        None,
+        stop_fade,
+    );
+    audio_engine.play_listener(playback.voice(voice));
+    queue.playing = Some(playback.info.id);
+
+    let earliest_next = Instant::now() + duration.saturating_sub(crossfade).max(MIN_TRACK_INTERVAL);
+    let generation = queue.generation;
+    let app_handle = app_handle.clone();
+    thread::spawn(move || {
+        wait_for_track_end(app_handle, playback, generation, ending_rx, earliest_next)
+    });
+
+    Ok(())
+}
+
+/// Moves on to the next track once this one is about to end, unless the queue has moved on
+/// already or the track was stopped from outside of it. A track that runs out early still
+/// holds its place until `earliest_next`, so broken files can't make the queue spin.
+fn wait_for_track_end(
+    app_handle: AppHandle,
+    playback: NewPlayback,
+    generation: u64,
+    ending_rx: mpsc::Receiver<()>,
+    earliest_next: Instant,
+) {
+    let mut ending = false;
+
+    loop {
+        if ending {
+            thread::sleep(
+                POLL_INTERVAL.min(earliest_next.saturating_duration_since(Instant::now())),
+            );
+        } else {
+            // The voice being dropped before it got there counts too, e.g. when it was stopped
+            ending = !matches!(
+                ending_rx.recv_timeout(POLL_INTERVAL),
+                Err(RecvTimeoutError::Timeout)
+            );
+        }
+
+        let settings_state = app_handle.state::<SettingsState>();
+        let settings_file = settings_state.settings_state.lock().unwrap();
+        let queue_state = app_handle.state::<QueueState>();
+        let mut queue = queue_state.queue.lock().unwrap();
+
+        if queue.generation != generation {
+            return;
+        }
+        if playback.is_stopped() {
+            // Stopped with stop all or `stop_sound`, which stops the whole queue
+            queue.generation += 1;
+            queue.playing = None;
+            emit_changed(&app_handle, &queue);
+            return;
+        }
+        if ending && Instant::now() >= earliest_next {
+            let next = queue.next_position(settings_file.queue.repeat, false);
+            if let Err(error) = play_position(&app_handle, &settings_file, &mut queue, next) {
+                eprintln!("Failed to play the next track: {}", error);
+            }
+            return;
+        }
+    }
+}
+
+/// Starts the queue, or stops it if it's playing. For the queue hotkey.
+pub fn toggle(app_handle: &AppHandle, settings_file: &SettingsFile) -> Result<(), AppError> {
+    let queue_state = app_handle.state::<QueueState>();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    if queue.playing.is_some() {
+        stop_current(app_handle, &mut queue);
+        emit_changed(app_handle, &queue);
+        Ok(())
+    } else {
+        let position = queue
+            .position
+            .or_else(|| queue.next_position(RepeatMode::Off, true));
+        play_position(app_handle, settings_file, &mut queue, position)
+    }
+}
+
+pub fn skip(app_handle: &AppHandle, settings_file: &SettingsFile) -> Result<(), AppError> {
+    let queue_state = app_handle.state::<QueueState>();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    let next = queue.next_position(settings_file.queue.repeat, true);
+    play_position(app_handle, settings_file, &mut queue, next)
+}
+
+pub fn previous(app_handle: &AppHandle, settings_file: &SettingsFile) -> Result<(), AppError> {
+    let queue_state = app_handle.state::<QueueState>();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    let previous = queue.previous_position(settings_file.queue.repeat);
+    play_position(app_handle, settings_file, &mut queue, previous)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_queue(queue_state: State<'_, QueueState>) -> QueueStatus {
+    queue_state.queue.lock().unwrap().status()
+}
+
+/// Adds sounds to the end of the queue. With shuffle on, they're shuffled into the tracks
+/// that haven't played yet.
+#[tauri::command(rename_all = "snake_case")]
+pub fn enqueue(
+    filenames: Vec<String>,
+    state: State<'_, SettingsState>,
+    queue_state: State<'_, QueueState>,
+    app_handle: AppHandle,
+) {
+    println!("Enqueueing {} tracks", filenames.len());
+
+    let settings_file = state.settings_state.lock().unwrap();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    for filename in filenames {
+        queue.tracks.push(filename);
+        let track = queue.tracks.len() - 1;
+
+        let upcoming = queue.position.map_or(0, |position| position + 1);
+        let index = if settings_file.queue.shuffle {
+            fastrand::usize(upcoming..=queue.order.len())
+        } else {
+            queue.order.len()
+        };
+        queue.order.insert(index, track);
+    }
+
+    emit_changed(&app_handle, &queue);
+}
+
+/// Removes the track at `position` in play order, moving on if it's the one playing.
+#[tauri::command(rename_all = "snake_case")]
+pub fn remove_from_queue(
+    position: usize,
+    state: State<'_, SettingsState>,
+    queue_state: State<'_, QueueState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    println!("Removing track {} from the queue", position);
+
+    let settings_file = state.settings_state.lock().unwrap();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    if position >= queue.order.len() {
+        return Ok(());
+    }
+    let track = queue.order.remove(position);
+    queue.tracks.remove(track);
+    for index in queue.order.iter_mut().filter(|index| **index > track) {
+        *index -= 1;
+    }
+
+    match queue.position {
+        Some(current) if current == position => {
+            // The track after the removed one has moved into its place
+            let next = (position < queue.order.len()).then_some(position);
+            if queue.playing.is_some() {
+                return play_position(&app_handle, &settings_file, &mut queue, next);
+            }
+            queue.position = next;
+        }
+        Some(current) if current > position => queue.position = Some(current - 1),
+        _ => {}
+    }
+
+    emit_changed(&app_handle, &queue);
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn clear_queue(queue_state: State<'_, QueueState>, app_handle: AppHandle) {
+    println!("Clearing the queue");
+
+    let mut queue = queue_state.queue.lock().unwrap();
+    stop_current(&app_handle, &mut queue);
+    queue.tracks.clear();
+    queue.order.clear();
+    queue.position = None;
+
+    emit_changed(&app_handle, &queue);
+}
+
+/// Plays the queue from the track at `position` in play order, or from where it left off.
+#[tauri::command(rename_all = "snake_case")]
+pub fn play_queue(
+    position: Option<usize>,
+    state: State<'_, SettingsState>,
+    queue_state: State<'_, QueueState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    let settings_file = state.settings_state.lock().unwrap();
+    let mut queue = queue_state.queue.lock().unwrap();
+
+    let position = position
+        .or(queue.position)
+        .or_else(|| queue.next_position(RepeatMode::Off, true));
+    play_position(&app_handle, &settings_file, &mut queue, position)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn stop_queue(queue_state: State<'_, QueueState>, app_handle: AppHandle) {
+    let mut queue = queue_state.queue.lock().unwrap();
+    stop_current(&app_handle, &mut queue);
+
+    emit_changed(&app_handle, &queue);
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn skip_track(state: State<'_, SettingsState>, app_handle: AppHandle) -> Result<(), AppError> {
+    skip(&app_handle, &state.settings_state.lock().unwrap())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn previous_track(
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<(), AppError> {
+    previous(&app_handle, &state.settings_state.lock().unwrap())
+}
+
+/// Saves shuffle, repeat and crossfade. Turning shuffle on or off reorders the queue around
+/// the current track without interrupting it.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_queue_settings(
+    queue_settings: QueueSettings,
+    state: State<'_, SettingsState>,
+    queue_state: State<'_, QueueState>,
+    app_handle: AppHandle,
+) -> Result<(), SettingsError> {
+    println!("Saving queue settings {:?}", queue_settings);
+
+    if queue_settings.crossfade_ms > fade::MAX_FADE_MS {
+        return Err(SettingsError::InvalidFade);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    if queue_settings.shuffle != settings_state.queue.shuffle {
+        let mut queue = queue_state.queue.lock().unwrap();
+        queue.reorder(queue_settings.shuffle);
+        emit_changed(&app_handle, &queue);
+    }
+    settings_state.queue = queue_settings;
+
+    write_settings_file(&settings_state)
+}
@@ -1,67 +1,490 @@
 use cpal::traits::{DeviceTrait, HostTrait};
+use rdev::Key;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use tauri::State;
+use std::sync::atomic::Ordering;
+use tauri::{AppHandle, State};
 
+use crate::effects::EffectConfig;
 use crate::errors::{AppError, SettingsError};
-use crate::{files, SettingsState};
+use crate::groups::SoundGroup;
+use crate::keybind::{self, Hotkey, Keybind};
+use crate::macros::SoundMacro;
+use crate::master_bus::{MasterBusSettings, MasterVolume, OutputBus};
+use crate::playback::PlayMode;
+use crate::profiles::{Profile, DEFAULT_PROFILE};
+use crate::queue::QueueSettings;
+use crate::trim::TrimPoints;
+use crate::{fade, files, loudness, volume, AudioEngineState, SettingsState};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct KeybindSetting {
     pub filename: String,
-    pub keybind: String,
+    #[serde(deserialize_with = "keybind::deserialize_optional")]
+    pub keybind: Option<Keybind>,
+    /// Percent, see `volume`
     #[serde(rename = "userVolume")]
     pub user_volume: f32,
     #[serde(rename = "listenerVolume")]
     pub listener_volume: f32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Applied in order, before the volume
+    #[serde(default)]
+    pub effects: Vec<EffectConfig>,
+    /// Plays the sound from here instead of from the start
+    #[serde(default, rename = "startMs")]
+    pub start_ms: Option<u64>,
+    /// Stops the sound here instead of at the end
+    #[serde(default, rename = "endMs")]
+    pub end_ms: Option<u64>,
+    #[serde(default, rename = "fadeInMs")]
+    pub fade_in_ms: u64,
+    #[serde(default, rename = "fadeOutMs")]
+    pub fade_out_ms: u64,
+    #[serde(default, rename = "playMode")]
+    pub play_mode: PlayMode,
+    /// Playing a sound stops every other sound in its choke group
+    #[serde(default, rename = "chokeGroup")]
+    pub choke_group: Option<String>,
+    /// Copies of this sound that can play at once, the oldest is stopped to make room
+    #[serde(default, rename = "maxPolyphony")]
+    pub max_polyphony: Option<u32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl KeybindSetting {
+    pub fn new(filename: String) -> Self {
+        KeybindSetting {
+            filename,
+            keybind: None,
+            user_volume: 100.0,
+            listener_volume: 100.0,
+            tags: Vec::new(),
+            effects: Vec::new(),
+            start_ms: None,
+            end_ms: None,
+            fade_in_ms: 0,
+            fade_out_ms: 0,
+            play_mode: PlayMode::default(),
+            choke_group: None,
+            max_polyphony: None,
+        }
+    }
+
+    /// Converts volumes saved before `settings_version` 1.
+    fn migrate(&mut self, settings_version: u32) {
+        if settings_version < 1 {
+            self.user_volume = volume::from_legacy(self.user_volume);
+            self.listener_volume = volume::from_legacy(self.listener_volume);
+        }
+    }
+}
+
+/// Bumped when saved values change meaning, so `SettingsFile::migrate` knows what to convert
+const SETTINGS_VERSION: u32 = 1;
+
+pub fn default_leader_key() -> Key {
+    Key::Alt
+}
+
+pub fn default_stop_fade_ms() -> u64 {
+    30
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct SettingsFile {
+    /// Used by every profile that doesn't have its own devices
     pub input_device: String,
     pub output_device: String,
-    pub noise_settings: Vec<KeybindSetting>,
+    #[serde(default)]
+    pub profiles: Vec<Profile>,
+    #[serde(default)]
+    pub active_profile: String,
+    /// Silences everything that's playing, pressed after the first key like any other keybind
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub stop_all_keybind: Option<Keybind>,
+    /// Switches to the profile after the active one
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub next_profile_keybind: Option<Keybind>,
+    /// Turn both master volumes up or down a step
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub volume_up_keybind: Option<Keybind>,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub volume_down_keybind: Option<Keybind>,
+    /// Mutes or unmutes both buses
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub mute_keybind: Option<Keybind>,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub queue_play_stop_keybind: Option<Keybind>,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub queue_next_keybind: Option<Keybind>,
+    #[serde(default, deserialize_with = "keybind::deserialize_optional")]
+    pub queue_previous_keybind: Option<Keybind>,
+    /// Held down before every keybind
+    #[serde(default = "default_leader_key")]
+    pub leader_key: Key,
+    /// Runs everything the call hears through an EQ tuned for voice codecs
+    #[serde(default)]
+    pub voice_chat_eq: bool,
+    /// Loudness every sound is brought to before its volume is applied, auto-gain is off
+    /// when `None`
+    #[serde(default)]
+    pub target_lufs: Option<f32>,
+    /// Limiter and compressor on the listener bus
+    #[serde(default)]
+    pub master_bus: MasterBusSettings,
+    #[serde(default)]
+    pub user_master_volume: MasterVolume,
+    #[serde(default)]
+    pub listener_master_volume: MasterVolume,
+    /// How long sounds take to fade out when they're stopped, so stopping doesn't click
+    #[serde(default = "default_stop_fade_ms")]
+    pub stop_fade_ms: u64,
+    /// Pools of sounds that play one of their sounds per press, shared by every profile
+    #[serde(default)]
+    pub sound_groups: Vec<SoundGroup>,
+    /// Sequences of sounds, shared by every profile
+    #[serde(default)]
+    pub macros: Vec<SoundMacro>,
+    /// How the background music queue moves from track to track
+    #[serde(default)]
+    pub queue: QueueSettings,
+    /// Sounds that can play at once, the oldest is stopped to make room
+    #[serde(default)]
+    pub max_polyphony: Option<u32>,
+    /// Files from before versioning count as 0
+    #[serde(default)]
+    pub settings_version: u32,
+    // Settings files from before profiles kept a single board at the top level
+    #[serde(default, rename = "noiseSettings", skip_serializing)]
+    legacy_noise_settings: Vec<KeybindSetting>,
 }
 
-#[tauri::command(rename_all = "snake_case")]
-pub async fn get_settings_file() -> Result<SettingsFile, AppError> {
-    println!("Loading settings");
+impl SettingsFile {
+    pub fn new(input_device: String, output_device: String) -> Self {
+        SettingsFile {
+            input_device,
+            output_device,
+            profiles: vec![Profile::new(DEFAULT_PROFILE.to_owned())],
+            active_profile: DEFAULT_PROFILE.to_owned(),
+            stop_all_keybind: None,
+            next_profile_keybind: None,
+            volume_up_keybind: None,
+            volume_down_keybind: None,
+            mute_keybind: None,
+            queue_play_stop_keybind: None,
+            queue_next_keybind: None,
+            queue_previous_keybind: None,
+            leader_key: default_leader_key(),
+            voice_chat_eq: false,
+            target_lufs: None,
+            master_bus: MasterBusSettings::default(),
+            user_master_volume: MasterVolume::default(),
+            listener_master_volume: MasterVolume::default(),
+            stop_fade_ms: default_stop_fade_ms(),
+            sound_groups: Vec::new(),
+            macros: Vec::new(),
+            queue: QueueSettings::default(),
+            max_polyphony: None,
+            settings_version: SETTINGS_VERSION,
+            legacy_noise_settings: Vec::new(),
+        }
+    }
 
-    let sound_folder = files::get_sounds_folder_path()?;
-    let settings_json_file = sound_folder.join("settings.json");
-    let settings_string = fs::read_to_string(&settings_json_file).unwrap();
-    let mut settings_file: SettingsFile =
-        serde_json::from_str(&settings_string).map_err(|_| SettingsError::DeserializeSettings)?;
+    /// Moves a board saved by an older version into its own profile.
+    pub fn migrate(&mut self) {
+        if self.profiles.is_empty() {
+            let mut profile = Profile::new(DEFAULT_PROFILE.to_owned());
+            profile.noise_settings = std::mem::take(&mut self.legacy_noise_settings);
+            self.profiles.push(profile);
+        }
 
-    let sound_files = files::get_sound_files(sound_folder);
-    let new_settings: Vec<KeybindSetting> = sound_files
-        .iter()
-        .map(|file_name| {
-            if let Some(existing_setting) = settings_file
-                .noise_settings
-                .iter_mut()
-                .find(|setting: &&mut KeybindSetting| setting.filename == *file_name)
-            {
-                existing_setting.clone()
-            } else {
-                KeybindSetting {
-                    filename: file_name.to_owned(),
-                    keybind: "?".to_owned(),
-                    user_volume: 1.0,
-                    listener_volume: 1.0,
-                }
+        for profile in self.profiles.iter_mut() {
+            for setting in profile.noise_settings.iter_mut() {
+                setting.migrate(self.settings_version);
             }
-        })
+        }
+        self.settings_version = SETTINGS_VERSION;
+
+        if self.find_profile(&self.active_profile).is_none() {
+            self.active_profile = self.profiles[0].name.clone();
+        }
+    }
+
+    pub fn find_profile(&self, name: &str) -> Option<usize> {
+        self.profiles
+            .iter()
+            .position(|profile| profile.name == name)
+    }
+
+    pub fn active_profile(&self) -> &Profile {
+        let index = self.find_profile(&self.active_profile).unwrap_or(0);
+        &self.profiles[index]
+    }
+
+    pub fn active_profile_mut(&mut self) -> &mut Profile {
+        let index = self.find_profile(&self.active_profile).unwrap_or(0);
+        &mut self.profiles[index]
+    }
+
+    /// The input and output devices the audio engine should use for the active profile
+    pub fn audio_devices(&self) -> (String, String) {
+        let profile = self.active_profile();
+
+        (
+            profile
+                .input_device
+                .clone()
+                .unwrap_or_else(|| self.input_device.clone()),
+            profile
+                .output_device
+                .clone()
+                .unwrap_or_else(|| self.output_device.clone()),
+        )
+    }
+
+    /// The active profile's setting for `filename`, or a new one if the file has none yet.
+    pub fn setting_or_default(&self, filename: &str) -> KeybindSetting {
+        self.active_profile()
+            .noise_settings
+            .iter()
+            .find(|setting| setting.filename == filename)
+            .cloned()
+            .unwrap_or_else(|| KeybindSetting::new(filename.to_owned()))
+    }
+
+    /// The active profile's setting for `filename`, added if the file is new.
+    pub fn noise_setting_mut(&mut self, filename: &str) -> &mut KeybindSetting {
+        let noise_settings = &mut self.active_profile_mut().noise_settings;
+
+        match noise_settings
+            .iter()
+            .position(|setting| setting.filename == filename)
+        {
+            Some(index) => &mut noise_settings[index],
+            None => {
+                noise_settings.push(KeybindSetting::new(filename.to_owned()));
+                noise_settings.last_mut().unwrap()
+            }
+        }
+    }
+
+    pub fn master_volume_mut(&mut self, bus: OutputBus) -> &mut MasterVolume {
+        match bus {
+            OutputBus::User => &mut self.user_master_volume,
+            OutputBus::Listener => &mut self.listener_master_volume,
+        }
+    }
+
+    pub fn hotkey(&self, hotkey: Hotkey) -> Option<&Keybind> {
+        match hotkey {
+            Hotkey::StopAll => self.stop_all_keybind.as_ref(),
+            Hotkey::NextProfile => self.next_profile_keybind.as_ref(),
+            Hotkey::VolumeUp => self.volume_up_keybind.as_ref(),
+            Hotkey::VolumeDown => self.volume_down_keybind.as_ref(),
+            Hotkey::Mute => self.mute_keybind.as_ref(),
+            Hotkey::QueuePlayStop => self.queue_play_stop_keybind.as_ref(),
+            Hotkey::QueueNext => self.queue_next_keybind.as_ref(),
+            Hotkey::QueuePrevious => self.queue_previous_keybind.as_ref(),
+        }
+    }
+
+    fn hotkey_mut(&mut self, hotkey: Hotkey) -> &mut Option<Keybind> {
+        match hotkey {
+            Hotkey::StopAll => &mut self.stop_all_keybind,
+            Hotkey::NextProfile => &mut self.next_profile_keybind,
+            Hotkey::VolumeUp => &mut self.volume_up_keybind,
+            Hotkey::VolumeDown => &mut self.volume_down_keybind,
+            Hotkey::Mute => &mut self.mute_keybind,
+            Hotkey::QueuePlayStop => &mut self.queue_play_stop_keybind,
+            Hotkey::QueueNext => &mut self.queue_next_keybind,
+            Hotkey::QueuePrevious => &mut self.queue_previous_keybind,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct KeybindConflict {
+    pub keybind: Keybind,
+    pub filenames: Vec<String>,
+}
+
+/// A keybind and what it's bound to
+struct Binding<'a> {
+    keybind: &'a Keybind,
+    /// The sound, group, macro or hotkey, as it's listed in conflicts
+    name: String,
+    /// The profile of a sound, `None` for groups, macros and hotkeys, which work in every profile
+    profile: Option<&'a str>,
+}
+
+impl<'a> Binding<'a> {
+    fn global(keybind: &'a Keybind, name: &str) -> Self {
+        Binding {
+            keybind,
+            name: name.to_owned(),
+            profile: None,
+        }
+    }
+
+    /// Sounds only clash with sounds in their own profile, everything else clashes everywhere
+    fn clashes_with(&self, other: &Binding) -> bool {
+        self.keybind == other.keybind
+            && (self.profile.is_none() || other.profile.is_none() || self.profile == other.profile)
+    }
+}
+
+/// Every keybind the listener responds to in any profile, along with the sound, group, macro
+/// or hotkey it's bound to. Sounds outside the active profile are named with their profile.
+fn bindings(settings_file: &SettingsFile) -> Vec<Binding<'_>> {
+    let mut bindings: Vec<Binding> = Vec::new();
+
+    for profile in settings_file.profiles.iter() {
+        let is_active = profile.name == settings_file.active_profile;
+        for setting in profile.active_noise_settings() {
+            if let Some(keybind) = setting.keybind.as_ref() {
+                bindings.push(Binding {
+                    keybind,
+                    name: if is_active {
+                        setting.filename.clone()
+                    } else {
+                        format!("{} ({})", setting.filename, profile.name)
+                    },
+                    profile: Some(&profile.name),
+                });
+            }
+        }
+    }
+
+    for group in settings_file.sound_groups.iter() {
+        if let Some(keybind) = group.keybind.as_ref() {
+            bindings.push(Binding::global(keybind, &group.name));
+        }
+    }
+
+    for sound_macro in settings_file.macros.iter() {
+        if let Some(keybind) = sound_macro.keybind.as_ref() {
+            bindings.push(Binding::global(keybind, &sound_macro.name));
+        }
+    }
+
+    for hotkey in Hotkey::ALL {
+        if let Some(keybind) = settings_file.hotkey(*hotkey) {
+            bindings.push(Binding::global(keybind, hotkey.name()));
+        }
+    }
+
+    bindings
+}
+
+fn keybind_conflicts(settings_file: &SettingsFile) -> Vec<KeybindConflict> {
+    let bindings = bindings(settings_file);
+    let mut conflicts: Vec<KeybindConflict> = Vec::new();
+
+    for (index, binding) in bindings.iter().enumerate() {
+        let clashes = bindings
+            .iter()
+            .enumerate()
+            .any(|(other_index, other)| other_index != index && binding.clashes_with(other));
+        if !clashes {
+            continue;
+        }
+
+        match conflicts
+            .iter_mut()
+            .find(|conflict| conflict.keybind == *binding.keybind)
+        {
+            Some(conflict) => conflict.filenames.push(binding.name.clone()),
+            None => conflicts.push(KeybindConflict {
+                keybind: binding.keybind.clone(),
+                filenames: vec![binding.name.clone()],
+            }),
+        }
+    }
+
+    conflicts
+}
+
+/// Fails if `keybind` clashes with anything other than `name`, which can also be a hotkey,
+/// group or macro name. `profile` is the profile of a sound, `None` for anything that works in
+/// every profile.
+pub fn validate_keybind(
+    settings_file: &SettingsFile,
+    name: &str,
+    profile: Option<&str>,
+    keybind: Option<&Keybind>,
+) -> Result<(), SettingsError> {
+    let Some(keybind) = keybind else {
+        return Ok(());
+    };
+    let binding = Binding {
+        keybind,
+        name: name.to_owned(),
+        profile,
+    };
+
+    let conflicting_names: Vec<String> = bindings(settings_file)
+        .into_iter()
+        // Its own current keybind is about to be replaced
+        .filter(|bound| !(bound.name == binding.name && bound.profile == binding.profile))
+        .filter(|bound| bound.clashes_with(&binding))
+        .map(|bound| bound.name)
         .collect();
 
-    settings_file.noise_settings = new_settings;
+    if conflicting_names.is_empty() {
+        Ok(())
+    } else {
+        Err(SettingsError::KeybindConflict(conflicting_names))
+    }
+}
 
-    let settings_string = serde_json::to_string_pretty(&settings_file)
+pub fn write_settings_file(settings_file: &SettingsFile) -> Result<(), SettingsError> {
+    let settings_json_file = files::get_sounds_folder_path()
+        .map_err(|_| SettingsError::LoadSoundsFolder)?
+        .join("settings.json");
+    let settings_string = serde_json::to_string_pretty(settings_file)
         .map_err(|_| SettingsError::SerializeSettings)?;
     fs::write(settings_json_file, settings_string).map_err(|_| SettingsError::WriteSettings)?;
 
-    Ok(settings_file)
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn get_settings_file(
+    state: State<'_, SettingsState>,
+    app_handle: AppHandle,
+) -> Result<SettingsFile, AppError> {
+    println!("Loading settings");
+
+    let sound_folder = files::get_sounds_folder_path()?;
+    let sound_files = files::get_sound_files(sound_folder);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+
+    // Every profile has a setting for each file in the sounds folder
+    for profile in settings_state.profiles.iter_mut() {
+        profile.noise_settings = sound_files
+            .iter()
+            .map(|file_name| {
+                if let Some(existing_setting) = profile
+                    .noise_settings
+                    .iter()
+                    .find(|setting| setting.filename == *file_name)
+                {
+                    existing_setting.clone()
+                } else {
+                    KeybindSetting::new(file_name.to_owned())
+                }
+            })
+            .collect();
+    }
+
+    write_settings_file(&settings_state)?;
+    loudness::analyze_in_background(app_handle, sound_files);
+
+    Ok(settings_state.clone())
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -74,44 +497,182 @@ pub async fn save_setting(
 ) -> Result<(), SettingsError> {
     println!("Saving setting for {}", file_name.clone());
 
-    // TODO: Consolidate keybinds and audio devices load + save functions
+    let keybind = keybind::parse_optional(&keybind)?;
+    if !volume::is_valid(user_volume) || !volume::is_valid(listener_volume) {
+        return Err(SettingsError::InvalidVolume);
+    }
 
-    let sound_folder =
-        files::get_sounds_folder_path().map_err(|_| SettingsError::LoadSoundsFolder)?;
-    let settings_json_file = sound_folder.join("settings.json");
-    let settings_string = fs::read_to_string(&settings_json_file).unwrap();
-    let mut settings_file: SettingsFile =
-        serde_json::from_str(&settings_string).map_err(|_| SettingsError::DeserializeSettings)?;
+    let mut settings_file = app_state.settings_state.lock().unwrap();
+    validate_keybind(
+        &settings_file,
+        &file_name,
+        Some(&settings_file.active_profile),
+        keybind.as_ref(),
+    )?;
 
-    let noise_settings = &mut settings_file.noise_settings;
+    let noise_settings = &mut settings_file.active_profile_mut().noise_settings;
 
     if let Some(existing_setting) = noise_settings
         .iter_mut()
         .find(|setting: &&mut KeybindSetting| setting.filename == file_name)
     {
-        existing_setting.keybind = keybind.to_owned();
+        existing_setting.keybind = keybind;
         existing_setting.user_volume = user_volume;
         existing_setting.listener_volume = listener_volume;
-        dbg!(existing_setting);
+        eprintln!("Updated setting {:?}", existing_setting);
     } else {
         noise_settings.push(KeybindSetting {
-            filename: file_name.to_owned(),
-            keybind: keybind.to_owned(),
+            keybind,
             user_volume,
             listener_volume,
+            ..KeybindSetting::new(file_name.to_owned())
         });
     }
 
-    // Save the settings to the app state
-    let mut mutex_settings = app_state.settings_state.lock().unwrap();
-    mutex_settings.noise_settings = noise_settings.to_vec();
-
     // Save the settings to the settings.json file
-    let settings_string = serde_json::to_string_pretty(&settings_file)
-        .map_err(|_| SettingsError::SerializeSettings)?;
-    fs::write(settings_json_file, settings_string).map_err(|_| SettingsError::WriteSettings)?;
+    write_settings_file(&settings_file)
+}
 
-    Ok(())
+/// Replaces the effect chain of a sound. Unknown effects are saved as they are.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_effects(
+    file_name: String,
+    effects: Vec<EffectConfig>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving effects for {}", file_name);
+
+    if effects.iter().any(EffectConfig::is_malformed)
+        || effects
+            .iter()
+            .filter_map(EffectConfig::effect)
+            .any(|effect| !effect.is_valid())
+    {
+        return Err(SettingsError::InvalidEffectSettings);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.noise_setting_mut(&file_name).effects = effects;
+
+    write_settings_file(&settings_state)
+}
+
+/// Sets where a sound starts and ends playing. `detect_trim_points` can propose them.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_trim(
+    file_name: String,
+    trim: TrimPoints,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving trim for {}", file_name);
+
+    if !trim.is_valid() {
+        return Err(SettingsError::InvalidTrim);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let setting = settings_state.noise_setting_mut(&file_name);
+    setting.start_ms = trim.start_ms;
+    setting.end_ms = trim.end_ms;
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_fades(
+    file_name: String,
+    fade_in_ms: u64,
+    fade_out_ms: u64,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving fades for {}", file_name);
+
+    if fade_in_ms > fade::MAX_FADE_MS || fade_out_ms > fade::MAX_FADE_MS {
+        return Err(SettingsError::InvalidFade);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let setting = settings_state.noise_setting_mut(&file_name);
+    setting.fade_in_ms = fade_in_ms;
+    setting.fade_out_ms = fade_out_ms;
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_play_mode(
+    file_name: String,
+    play_mode: PlayMode,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving play mode {:?} for {}", play_mode, file_name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.noise_setting_mut(&file_name).play_mode = play_mode;
+
+    write_settings_file(&settings_state)
+}
+
+/// Sets a sound's choke group and how many copies of it can play at once. `None` turns
+/// either off.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_voice_limits(
+    file_name: String,
+    choke_group: Option<String>,
+    max_polyphony: Option<u32>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving voice limits for {}", file_name);
+
+    if max_polyphony == Some(0) {
+        return Err(SettingsError::InvalidPolyphony);
+    }
+    let choke_group = choke_group
+        .map(|choke_group| choke_group.trim().to_owned())
+        .filter(|choke_group| !choke_group.is_empty());
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let setting = settings_state.noise_setting_mut(&file_name);
+    setting.choke_group = choke_group;
+    setting.max_polyphony = max_polyphony;
+
+    write_settings_file(&settings_state)
+}
+
+/// Sets how many sounds can play at once across the whole board, `None` for no limit.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_max_polyphony(
+    max_polyphony: Option<u32>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving max polyphony {:?}", max_polyphony);
+
+    if max_polyphony == Some(0) {
+        return Err(SettingsError::InvalidPolyphony);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.max_polyphony = max_polyphony;
+
+    write_settings_file(&settings_state)
+}
+
+/// Sets how long sounds fade out for when they're stopped, from the next sound played on.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_stop_fade(
+    stop_fade_ms: u64,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving stop fade of {}ms", stop_fade_ms);
+
+    if stop_fade_ms > fade::MAX_FADE_MS {
+        return Err(SettingsError::InvalidFade);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.stop_fade_ms = stop_fade_ms;
+
+    write_settings_file(&settings_state)
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -139,6 +700,7 @@ pub async fn save_audio_devices(
     input_device: String,
     output_device: String,
     state: State<'_, SettingsState>,
+    engine_state: State<'_, AudioEngineState>,
 ) -> Result<(), SettingsError> {
     println!("Saving audio devices");
 
@@ -146,12 +708,86 @@ pub async fn save_audio_devices(
     settings_state.input_device = input_device;
     settings_state.output_device = output_device;
 
-    let settings_file = files::get_sounds_folder_path()
-        .map_err(|_| SettingsError::LoadSoundsFolder)?
-        .join("settings.json");
-    let settings_string = serde_json::to_string_pretty(&*settings_state)
-        .map_err(|_| SettingsError::SerializeSettings)?;
-    fs::write(settings_file, settings_string).map_err(|_| SettingsError::WriteSettings)?;
+    let (input_device, output_device) = settings_state.audio_devices();
+    engine_state.restart(input_device, output_device);
 
-    Ok(())
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_hotkey(
+    hotkey: Hotkey,
+    keybind: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving {} hotkey", hotkey.name());
+
+    let keybind = keybind::parse_optional(&keybind)?;
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    validate_keybind(&settings_state, hotkey.name(), None, keybind.as_ref())?;
+    *settings_state.hotkey_mut(hotkey) = keybind;
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn find_keybind_conflicts(state: State<'_, SettingsState>) -> Vec<KeybindConflict> {
+    keybind_conflicts(&state.settings_state.lock().unwrap())
+}
+
+/// The leader key named the way keybinds name their keys, e.g. "Ctrl" rather than "ControlLeft"
+#[tauri::command(rename_all = "snake_case")]
+pub fn get_leader_key(state: State<'_, SettingsState>) -> String {
+    keybind::display_key(state.settings_state.lock().unwrap().leader_key)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_leader_key(
+    leader_key: Key,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Saving leader key {:?}", leader_key);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.leader_key = leader_key;
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_voice_chat_eq(
+    enabled: bool,
+    state: State<'_, SettingsState>,
+    engine_state: State<'_, AudioEngineState>,
+) -> Result<(), SettingsError> {
+    println!("Setting voice chat EQ to {}", enabled);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.voice_chat_eq = enabled;
+    engine_state
+        .bus_controls
+        .voice_chat_eq
+        .store(enabled, Ordering::Relaxed);
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn save_master_bus_settings(
+    master_bus: MasterBusSettings,
+    state: State<'_, SettingsState>,
+    engine_state: State<'_, AudioEngineState>,
+) -> Result<(), SettingsError> {
+    println!("Saving master bus settings");
+
+    if !master_bus.is_valid() {
+        return Err(SettingsError::InvalidEffectSettings);
+    }
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.master_bus = master_bus.clone();
+    engine_state.bus_controls.set_master_bus(master_bus);
+
+    write_settings_file(&settings_state)
 }
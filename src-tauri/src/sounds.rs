@@ -1,151 +1,217 @@
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use lofty::{AudioFile, Probe};
-use ringbuf::HeapRb;
-use rodio::{Decoder, OutputStream, Sink};
+use rodio::{Decoder, Source};
+use std::path::Path;
+use std::time::Duration;
 use std::{fs::File, io::BufReader};
-use tauri::State;
+use tauri::{AppHandle, Manager, State};
 
+use crate::effects::{self, BoxedSource};
 use crate::errors::{AppError, SoundsError};
-use crate::files;
-use crate::{files::get_sounds_folder_path, SettingsState};
+use crate::loudness::LoudnessState;
+use crate::playback::{PlayMode, PlaybackId, PlaybackInfo, PlaybackKind, PlaybackRegistry};
+use crate::settings::{KeybindSetting, SettingsFile};
+use crate::{fade, files::get_sounds_folder_path, trim, volume, AudioEngineState, SettingsState};
 
+/// How long the sound plays for in seconds, taking its trim points and effects that change
+/// its speed into account
 #[tauri::command(rename_all = "snake_case")]
-pub async fn get_sound_duration(filename: String) -> Result<u64, AppError> {
-    let sound_folder_path = get_sounds_folder_path()?;
-    let sound_file_path = sound_folder_path.join(filename);
+pub async fn get_sound_duration(
+    filename: String,
+    state: State<'_, SettingsState>,
+) -> Result<u64, AppError> {
+    let settings_state = state.settings_state.lock().unwrap();
+    let setting = settings_state.setting_or_default(&filename);
+
+    Ok(sound_duration(&setting)?.as_secs())
+}
+
+/// How long a sound plays for once trimmed and run through its effects.
+pub fn sound_duration(setting: &KeybindSetting) -> Result<Duration, AppError> {
+    let sound_file_path = get_sounds_folder_path()?.join(&setting.filename);
 
     if !sound_file_path.is_file() {
         return Err(SoundsError::LoadSoundFile.into());
     }
 
-    let tagged_file = Probe::open(&sound_file_path)
+    let duration = trim::duration(probe_duration(&sound_file_path)?, setting);
+    Ok(effects::chain_duration(duration, &setting.effects))
+}
+
+/// Reads the length of a sound file from its metadata, without decoding it.
+fn probe_duration(path: &Path) -> Result<Duration, SoundsError> {
+    let tagged_file = Probe::open(path)
         .map_err(|_| SoundsError::OpenSoundFilePath)?
         .read()
         .map_err(|_| SoundsError::LoadSoundFile)?;
 
-    Ok(tagged_file.properties().duration().as_secs())
+    Ok(tagged_file.properties().duration())
+}
+
+/// Decodes a sound file into samples the audio engine can mix.
+fn decode_sound(path_to_sound: &str) -> Result<impl Source<Item = f32> + Send, SoundsError> {
+    let file = File::open(path_to_sound).map_err(|_| SoundsError::LoadSoundFile)?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|_| SoundsError::DecodeSoundFile)?;
+
+    Ok(decoder.convert_samples())
+}
+
+/// Builds the voice for one bus. Both buses go through the same effects so what the user
+/// previews is what the call hears.
+fn build_voice(
+    path_to_sound: &str,
+    setting: &KeybindSetting,
+    duration: Duration,
+    auto_gain: f32,
+    volume: f32,
+) -> Result<BoxedSource, SoundsError> {
+    let source = decode_sound(path_to_sound)?.amplify(auto_gain);
+    let mut source = fade::apply(trim::apply(source, setting), setting, duration);
+    if setting.play_mode == PlayMode::Loop {
+        // Loops before the effects, so echoes and reverb carry over into the next repeat
+        source = Box::new(source.buffered().repeat_infinite());
+    }
+    let voice = effects::apply_chain(source, &setting.effects);
+
+    Ok(Box::new(voice.amplify(volume::to_gain(volume))))
+}
+
+/// What every voice of a sound is built from: the path to its file, its auto-gain and how
+/// long it plays once trimmed
+fn voice_inputs(
+    app_handle: &AppHandle,
+    target_lufs: Option<f32>,
+    setting: &KeybindSetting,
+) -> Result<(String, f32, Duration), AppError> {
+    let path_to_sound = get_sounds_folder_path()?
+        .join(&setting.filename)
+        .into_os_string()
+        .into_string()
+        .map_err(|_| SoundsError::OpenSoundFilePath)?;
+
+    let auto_gain = app_handle
+        .state::<LoudnessState>()
+        .auto_gain(target_lufs, &setting.filename);
+
+    // Only fading out needs to know where the sound ends
+    let duration = match setting.fade_out_ms {
+        0 => Duration::ZERO,
+        _ => trim::duration(probe_duration(Path::new(&path_to_sound))?, setting),
+    };
+
+    Ok((path_to_sound, auto_gain, duration))
+}
+
+/// Builds a sound's listener and user voices, ready to be handed to the engine. Auto-gain
+/// aims for `target_lufs`, the settings file's target.
+pub fn build_voices(
+    app_handle: &AppHandle,
+    target_lufs: Option<f32>,
+    setting: &KeybindSetting,
+) -> Result<(BoxedSource, BoxedSource), AppError> {
+    let (path_to_sound, auto_gain, duration) = voice_inputs(app_handle, target_lufs, setting)?;
+
+    // Each bus gets its own decoder so the two outputs can be processed independently
+    let listener_source = build_voice(
+        &path_to_sound,
+        setting,
+        duration,
+        auto_gain,
+        setting.listener_volume,
+    )?;
+    let user_source = build_voice(
+        &path_to_sound,
+        setting,
+        duration,
+        auto_gain,
+        setting.user_volume,
+    )?;
+
+    Ok((listener_source, user_source))
+}
+
+/// Builds only the listener voice of a sound, for things the call hears on its own.
+pub fn build_listener_voice(
+    app_handle: &AppHandle,
+    target_lufs: Option<f32>,
+    setting: &KeybindSetting,
+) -> Result<BoxedSource, AppError> {
+    let (path_to_sound, auto_gain, duration) = voice_inputs(app_handle, target_lufs, setting)?;
+
+    Ok(build_voice(
+        &path_to_sound,
+        setting,
+        duration,
+        auto_gain,
+        setting.listener_volume,
+    )?)
+}
+
+/// Stops what has to make way for `setting` before it plays: the rest of its choke group, and
+/// the oldest playbacks over the sound's polyphony limit or `max_total`.
+pub fn make_room_for(
+    playbacks: &PlaybackRegistry,
+    setting: &KeybindSetting,
+    max_total: Option<u32>,
+) {
+    if let Some(choke_group) = &setting.choke_group {
+        playbacks.choke(choke_group);
+    }
+    playbacks.make_room(&setting.filename, setting.max_polyphony, max_total);
 }
 
 pub fn make_some_noise(
-    path_to_sound: String,
-    user_volume: f32,
-    listener_volume: f32,
-    in_device: String,
-    out_device: String,
-) -> Result<(), SoundsError> {
-    std::thread::spawn(move || {
-        // Open the audio file
-        let file = File::open(&path_to_sound).map_err(|_| SoundsError::LoadSoundFile)?;
-        let host = cpal::default_host();
-
-        let input_device = host
-            .input_devices()
-            .unwrap()
-            .find(|device| device.name().unwrap_or("".to_owned()).contains(&out_device))
-            .expect("Failed to find audio input device");
-
-        let output_device = host
-            .output_devices()
-            .unwrap()
-            .find(|device| device.name().unwrap_or("".to_owned()).contains(&in_device))
-            .expect("Failed to find audio output device");
-
-        let config: cpal::StreamConfig = input_device.default_input_config().unwrap().into();
-        let output_config: cpal::StreamConfig =
-            output_device.default_output_config().unwrap().into();
-
-        // Stream audio file to the mic
-        let (_stream, stream_handle) = OutputStream::try_from_device(&output_device).unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-        sink.set_volume(listener_volume / 1000.0);
-        let reader = BufReader::new(file);
-        sink.append(Decoder::new(reader).unwrap());
-
-        // Stream audio to headset
-        let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-        let sink = Sink::try_new(&stream_handle).unwrap();
-        sink.set_volume(user_volume / 1000.0);
-        let file = File::open(&path_to_sound).unwrap();
-        let reader = BufReader::new(file);
-        sink.append(Decoder::new(reader).unwrap());
-
-        let latency_frames = (50.0 / 1_000.0) * config.sample_rate.0 as f32;
-        let latency_samples = latency_frames as usize * config.channels as usize;
-
-        // The buffer to share samples
-        let ring = HeapRb::<f32>::new(latency_samples * 2);
-        let (mut producer, mut consumer) = ring.split();
-
-        for _ in 0..latency_samples {
-            // The ring buffer has 2x as much space as necessary to add latency here,
-            // so this should never fail
-            producer.push(0.0).unwrap();
-        }
+    app_handle: &AppHandle,
+    settings_file: &SettingsFile,
+    setting: &KeybindSetting,
+) -> Result<PlaybackId, AppError> {
+    let (listener_source, user_source) =
+        build_voices(app_handle, settings_file.target_lufs, setting)?;
 
-        // This controls audio stream to mic
-        let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
-            let mut input_fell_behind = false;
-            for sample in data {
-                *sample = match consumer.pop() {
-                    // This ratio is the amount of echo (100% == full echo)
-                    // Caused by the ring buffer being 2x required size
-                    Some(s) => s * (0.1 / 100.0),
-                    None => {
-                        input_fell_behind = true;
-                        0.0
-                    }
-                };
-            }
-            if input_fell_behind {
-                eprintln!("input stream fell behind: try increasing latency");
-            }
-        };
-        let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
-            let mut output_fell_behind = false;
-            for &sample in data {
-                if producer.push(sample).is_err() {
-                    output_fell_behind = true;
-                }
-            }
-            if output_fell_behind {
-                eprintln!("output stream fell behind: try increasing latency");
+    let engine_state = app_handle.state::<AudioEngineState>();
+    let audio_engine = engine_state.audio_engine.lock().unwrap();
+    let audio_engine = audio_engine
+        .as_ref()
+        .ok_or(SoundsError::EngineUnavailable)?;
+
+    make_room_for(
+        &engine_state.playbacks,
+        setting,
+        settings_file.max_polyphony,
+    );
+
+    let playback = engine_state.playbacks.register(
+        setting.filename.clone(),
+        PlaybackKind::Sound,
+        setting.choke_group.clone(),
+        Duration::from_millis(settings_file.stop_fade_ms),
+    );
+    audio_engine.play(playback.voice(listener_source), playback.voice(user_source));
+
+    Ok(playback.info.id)
+}
+
+/// Plays a sound the way its play mode says a keybind press should. Returns the playback
+/// that was started, if any.
+pub fn trigger_sound(
+    app_handle: &AppHandle,
+    settings_file: &SettingsFile,
+    setting: &KeybindSetting,
+) -> Result<Option<PlaybackId>, AppError> {
+    let playbacks = &app_handle.state::<AudioEngineState>().playbacks;
+
+    match setting.play_mode {
+        PlayMode::Overlap | PlayMode::Hold => {}
+        PlayMode::Restart => {
+            playbacks.stop_file(&setting.filename);
+        }
+        PlayMode::Toggle | PlayMode::Loop => {
+            if playbacks.stop_file(&setting.filename) {
+                return Ok(None);
             }
-        };
-
-        // Initialize the audio input stream to capture the audio from the Virtual Audio Device output
-        let config = input_device.default_input_config().unwrap().into();
-        let input_stream = input_device
-            .build_input_stream(
-                &config,
-                input_data_fn,
-                |err| {
-                    eprintln!("Error occurred in input stream: {}", err);
-                },
-                None,
-            )
-            .unwrap();
-
-        // Start the audio input stream
-        let output_stream = output_device
-            .build_output_stream(
-                &output_config,
-                output_data_fn,
-                |err| {
-                    eprintln!("an error occurred on stream: {}", err);
-                },
-                None,
-            )
-            .unwrap();
-
-        input_stream.play().unwrap();
-        output_stream.play().unwrap();
-
-        sink.sleep_until_end();
-
-        Ok::<(), SoundsError>(())
-    });
+        }
+    }
 
-    Ok(())
+    make_some_noise(app_handle, settings_file, setting).map(Some)
 }
 
 #[tauri::command(rename_all = "snake_case")]
@@ -154,25 +220,37 @@ pub fn play_sound(
     user_volume: f32,
     listener_volume: f32,
     state: State<'_, SettingsState>,
-) {
-    let input_device = state.settings_state.lock().unwrap().input_device.clone();
-    let output_device = state.settings_state.lock().unwrap().output_device.clone();
+    app_handle: AppHandle,
+) -> Result<PlaybackId, AppError> {
+    let settings_state = state.settings_state.lock().unwrap();
 
-    let path_to_sound = files::get_sounds_folder_path()
-        .expect("Failed to get sounds folder path")
-        .join(filename)
-        .as_path()
-        .to_str()
-        .unwrap()
-        .to_string();
+    // Previews the saved effects, but with the volumes currently in the table
+    let mut setting = settings_state.setting_or_default(&filename);
+    setting.user_volume = user_volume;
+    setting.listener_volume = listener_volume;
 
     // Callable via Tauri command from React on 'preview' button, vs direct invocation via keybind listener
-    make_some_noise(
-        path_to_sound,
-        user_volume,
-        listener_volume,
-        input_device,
-        output_device,
-    )
-    .expect("Failed to play sound")
+    make_some_noise(&app_handle, &settings_state, &setting)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn stop_sound(
+    id: PlaybackId,
+    engine_state: State<'_, AudioEngineState>,
+) -> Result<(), SoundsError> {
+    if !engine_state.playbacks.stop(id) {
+        return Err(SoundsError::PlaybackNotFound);
+    }
+
+    Ok(())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn stop_all_sounds(engine_state: State<'_, AudioEngineState>) {
+    engine_state.playbacks.stop_all();
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn list_playing(engine_state: State<'_, AudioEngineState>) -> Vec<PlaybackInfo> {
+    engine_state.playbacks.list()
 }
@@ -0,0 +1,103 @@
+use tauri::State;
+
+use crate::errors::SettingsError;
+use crate::settings::{write_settings_file, KeybindSetting};
+use crate::SettingsState;
+
+fn validate_tag(tag: &str) -> Result<String, SettingsError> {
+    let tag = tag.trim();
+
+    if tag.is_empty() {
+        return Err(SettingsError::InvalidTag);
+    }
+
+    Ok(tag.to_owned())
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn add_tag(
+    file_name: String,
+    tag: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Adding tag {} to {}", tag, file_name);
+
+    let tag = validate_tag(&tag)?;
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    let setting = settings_state.noise_setting_mut(&file_name);
+
+    if !setting.tags.contains(&tag) {
+        setting.tags.push(tag);
+    }
+
+    write_settings_file(&settings_state)
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub async fn remove_tag(
+    file_name: String,
+    tag: String,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Removing tag {} from {}", tag, file_name);
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+
+    if let Some(setting) = settings_state
+        .active_profile_mut()
+        .noise_settings
+        .iter_mut()
+        .find(|setting| setting.filename == file_name)
+    {
+        setting.tags.retain(|existing_tag| *existing_tag != tag);
+    }
+
+    write_settings_file(&settings_state)
+}
+
+/// Every tag used in the active profile, sorted alphabetically
+#[tauri::command(rename_all = "snake_case")]
+pub fn list_tags(state: State<'_, SettingsState>) -> Vec<String> {
+    let settings_state = state.settings_state.lock().unwrap();
+
+    let mut tags: Vec<String> = settings_state
+        .active_profile()
+        .noise_settings
+        .iter()
+        .flat_map(|setting| setting.tags.iter().cloned())
+        .collect();
+    tags.sort();
+    tags.dedup();
+
+    tags
+}
+
+#[tauri::command(rename_all = "snake_case")]
+pub fn list_sounds_by_tag(tag: String, state: State<'_, SettingsState>) -> Vec<KeybindSetting> {
+    let settings_state = state.settings_state.lock().unwrap();
+
+    settings_state
+        .active_profile()
+        .noise_settings
+        .iter()
+        .filter(|setting| setting.tags.contains(&tag))
+        .cloned()
+        .collect()
+}
+
+/// Limits keybinds to the sounds with `tag`, or lets every sound respond again when `None`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn set_active_tag(
+    tag: Option<String>,
+    state: State<'_, SettingsState>,
+) -> Result<(), SettingsError> {
+    println!("Setting active tag to {:?}", tag);
+
+    let tag = tag.as_deref().map(validate_tag).transpose()?;
+
+    let mut settings_state = state.settings_state.lock().unwrap();
+    settings_state.active_profile_mut().active_tag = tag;
+
+    write_settings_file(&settings_state)
+}
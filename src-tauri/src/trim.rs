@@ -0,0 +1,112 @@
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::effects::BoxedSource;
+use crate::errors::{AppError, SoundsError};
+use crate::files::get_sounds_folder_path;
+use crate::settings::KeybindSetting;
+
+const DEFAULT_SILENCE_THRESHOLD_DB: f32 = -50.0;
+const MIN_SILENCE_THRESHOLD_DB: f32 = -96.0;
+/// Kept before the first loud sample, so attacks aren't clipped
+const START_PADDING_MS: u64 = 10;
+/// Kept after the last loud sample, so quiet tails can ring out
+const END_PADDING_MS: u64 = 50;
+
+/// Where a sound should start and end, `None` plays it from the start or to the end
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TrimPoints {
+    pub start_ms: Option<u64>,
+    pub end_ms: Option<u64>,
+}
+
+impl TrimPoints {
+    pub fn is_valid(&self) -> bool {
+        match (self.start_ms, self.end_ms) {
+            (Some(start_ms), Some(end_ms)) => start_ms < end_ms,
+            _ => true,
+        }
+    }
+}
+
+/// Skips to the setting's start point and stops at its end point.
+pub fn apply<S>(source: S, setting: &KeybindSetting) -> BoxedSource
+where
+    S: Source<Item = f32> + Send + 'static,
+{
+    let start = Duration::from_millis(setting.start_ms.unwrap_or(0));
+    let source = source.skip_duration(start);
+
+    match setting.end_ms {
+        Some(end_ms) => {
+            Box::new(source.take_duration(Duration::from_millis(end_ms).saturating_sub(start)))
+        }
+        None => Box::new(source),
+    }
+}
+
+/// How long the part of the sound between the trim points is.
+pub fn duration(duration: Duration, setting: &KeybindSetting) -> Duration {
+    let end = setting.end_ms.map_or(duration, |end_ms| {
+        Duration::from_millis(end_ms).min(duration)
+    });
+
+    end.saturating_sub(Duration::from_millis(setting.start_ms.unwrap_or(0)))
+}
+
+/// Finds the first and last samples louder than the threshold.
+fn detect_silence(path: &Path, threshold_db: f32) -> Result<TrimPoints, SoundsError> {
+    let file = File::open(path).map_err(|_| SoundsError::LoadSoundFile)?;
+    let decoder = Decoder::new(BufReader::new(file)).map_err(|_| SoundsError::DecodeSoundFile)?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate().max(1) as u64;
+    let threshold = 10f32.powf(threshold_db / 20.0);
+
+    let mut first_loud = None;
+    let mut last_loud = None;
+    let mut frames = 0;
+    for (index, sample) in decoder.convert_samples::<f32>().enumerate() {
+        let frame = (index / channels) as u64;
+        if sample.abs() >= threshold {
+            first_loud.get_or_insert(frame);
+            last_loud = Some(frame);
+        }
+        frames = frame + 1;
+    }
+
+    let (Some(first_loud), Some(last_loud)) = (first_loud, last_loud) else {
+        return Err(SoundsError::SilentSound);
+    };
+
+    let to_ms = |frame: u64| frame * 1000 / sample_rate;
+    let start_ms = to_ms(first_loud).saturating_sub(START_PADDING_MS);
+    let end_ms = to_ms(last_loud + 1) + END_PADDING_MS;
+
+    Ok(TrimPoints {
+        start_ms: (start_ms > 0).then_some(start_ms),
+        end_ms: (end_ms < to_ms(frames)).then_some(end_ms),
+    })
+}
+
+/// Proposes trim points that cut off leading and trailing silence. Nothing is saved, the
+/// points can be adjusted and then saved with `save_trim`.
+#[tauri::command(rename_all = "snake_case")]
+pub async fn detect_trim_points(
+    filename: String,
+    threshold_db: Option<f32>,
+) -> Result<TrimPoints, AppError> {
+    println!("Detecting silence in {}", filename);
+
+    let threshold_db = threshold_db.unwrap_or(DEFAULT_SILENCE_THRESHOLD_DB);
+    if !(MIN_SILENCE_THRESHOLD_DB..=0.0).contains(&threshold_db) {
+        return Err(SoundsError::InvalidSilenceThreshold.into());
+    }
+
+    let path = get_sounds_folder_path()?.join(&filename);
+    Ok(detect_silence(&path, threshold_db)?)
+}
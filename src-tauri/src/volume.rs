@@ -0,0 +1,36 @@
+//! Every volume in the app is a percentage, where 100% plays a sound as it is. The
+//! percentage is mapped onto gain with a curve that matches how loud it sounds: halving
+//! the volume is 10 dB quieter, which is heard as about half as loud.
+
+pub const MAX_PERCENT: f32 = 200.0;
+
+/// Decibels per doubling of the percentage
+const DB_PER_DOUBLING: f32 = 10.0;
+
+/// `gain = (percent / 100) ^ exponent`
+fn curve_exponent() -> f32 {
+    DB_PER_DOUBLING / (20.0 * 2f32.log10())
+}
+
+pub fn is_valid(percent: f32) -> bool {
+    (0.0..=MAX_PERCENT).contains(&percent)
+}
+
+pub fn to_gain(percent: f32) -> f32 {
+    (percent.clamp(0.0, MAX_PERCENT) / 100.0).powf(curve_exponent())
+}
+
+pub fn from_gain(gain: f32) -> f32 {
+    (100.0 * gain.max(0.0).powf(curve_exponent().recip())).min(MAX_PERCENT)
+}
+
+/// Volumes used to be divided by 1000 when played, so a saved 100 was a gain of 0.1.
+/// Converts one to the percentage that plays at the same loudness. A volume of exactly 1.0
+/// is the default nobody changed, so it becomes 100% rather than near silence.
+pub fn from_legacy(volume: f32) -> f32 {
+    if volume == 1.0 {
+        return 100.0;
+    }
+
+    (from_gain(volume / 1000.0) * 10.0).round() / 10.0
+}
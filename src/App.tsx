@@ -1,11 +1,13 @@
 import { useEffect, useState } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
+import { listen } from "@tauri-apps/api/event";
 import Navbar from "./components/Navbar";
 import NoiseTable from "./components/NoiseTable";
 import { SettingsFile } from "./types";
 
 function App() {
 	const [settingsFile, setSettingsFile] = useState<SettingsFile | null>(null);
+	const [leaderKey, setLeaderKey] = useState<string | undefined>(undefined);
 	const [allAudioDevices, setAllAudioDevices] = useState<[string[], string[]]>([
 		[],
 		[],
@@ -16,6 +18,9 @@ function App() {
 		const settingsFile: SettingsFile = await invoke("get_settings_file");
 		console.info("Settings file", settingsFile);
 		setSettingsFile(settingsFile);
+		// settingsFile.leaderKey is the raw key, this is the name keybinds use for it
+		const leaderKey: string = await invoke("get_leader_key");
+		setLeaderKey(leaderKey);
 	}
 
 	useEffect(() => {
@@ -28,8 +33,26 @@ function App() {
 
 		getSettingsFile();
 		loadAudioDevices();
+
+		// Profiles can also be switched by hotkey while the app is in the background
+		const unlistenProfileChanged = listen("profile-changed", () => {
+			getSettingsFile();
+		});
+
+		return () => {
+			unlistenProfileChanged.then((unlisten) => unlisten());
+		};
 	}, []);
 
+	async function activateProfile(name: string) {
+		await invoke("activate_profile", { name });
+		await getSettingsFile();
+	}
+
+	const activeProfile = settingsFile?.profiles.find(
+		(profile) => profile.name === settingsFile.activeProfile
+	);
+
 	return (
 		<div className="m-0 flex h-lvh w-lvw flex-col justify-center text-center bg-gradient-to-b from-neutral-800 to-black text-white">
 			<Navbar
@@ -37,7 +60,23 @@ function App() {
 				handleRefresh={() => getSettingsFile()}
 			/>
 
-			{settingsFile?.noiseSettings.length === 0 ? (
+			{settingsFile && settingsFile.profiles.length > 1 ? (
+				<div className="flex justify-center mb-2">
+					<select
+						className="bg-black text-white px-1 py-0.5"
+						value={settingsFile.activeProfile}
+						onChange={(e) => activateProfile(e.target.value)}
+					>
+						{settingsFile.profiles.map((profile) => {
+							return <option key={profile.name}>{profile.name}</option>;
+						})}
+					</select>
+				</div>
+			) : (
+				<></>
+			)}
+
+			{activeProfile?.noiseSettings.length === 0 ? (
 				<div className="w-full h-full flex flex-col justify-center items-center">
 					<h1 className="text-2xl">No sound files found</h1>
 					<p>
@@ -46,7 +85,10 @@ function App() {
 				</div>
 			) : (
 				<section className="flex justify-center">
-					<NoiseTable noiseSettings={settingsFile?.noiseSettings} />
+					<NoiseTable
+						noiseSettings={activeProfile?.noiseSettings}
+						leaderKey={leaderKey}
+					/>
 				</section>
 			)}
 		</div>
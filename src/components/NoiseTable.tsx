@@ -1,16 +1,25 @@
 import { invoke } from "@tauri-apps/api";
-import { Setting } from "../types";
+import { listen } from "@tauri-apps/api/event";
+import { KeybindConflict, Playback, Setting } from "../types";
 import { useEffect, useState } from "react";
 
 interface NoiseTable {
 	noiseSettings: Setting[] | undefined;
+	leaderKey: string | undefined;
 }
 
-const NoiseTable = ({ noiseSettings }: NoiseTable) => {
+const NoiseTable = ({ noiseSettings, leaderKey }: NoiseTable) => {
 	console.info("Noise settings", noiseSettings);
 
 	const [settings, setSettings] = useState<Setting[] | undefined>(undefined);
 	const [durations, setDurations] = useState<number[]>([]);
+	const [playing, setPlaying] = useState<Playback[]>([]);
+	const [conflicts, setConflicts] = useState<KeybindConflict[]>([]);
+
+	async function findKeybindConflicts() {
+		const conflicts: KeybindConflict[] = await invoke("find_keybind_conflicts");
+		setConflicts(conflicts);
+	}
 
 	async function playSound(setting: Setting) {
 		await invoke("play_sound", {
@@ -20,6 +29,14 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 		});
 	}
 
+	async function stopSound(filename: string) {
+		await Promise.all(
+			playing
+				.filter((playback) => playback.filename === filename)
+				.map((playback) => invoke("stop_sound", { id: playback.id }))
+		);
+	}
+
 	async function handleSaveSetting(selectedSetting: Setting) {
 		const matchingRow = settings?.find((setting) => {
 			return setting.filename == selectedSetting.filename;
@@ -33,19 +50,43 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 				matchingRow.userVolume,
 				matchingRow.listenerVolume
 			);
-			await invoke("save_setting", {
-				file_name: matchingRow.filename,
-				keybind: matchingRow.keybind?.toUpperCase(),
-				user_volume: matchingRow.userVolume,
-				listener_volume: matchingRow.listenerVolume,
-			});
+			try {
+				await invoke("save_setting", {
+					file_name: matchingRow.filename,
+					keybind: matchingRow.keybind?.toUpperCase() ?? "?",
+					user_volume: matchingRow.userVolume,
+					listener_volume: matchingRow.listenerVolume,
+				});
+			} catch (error) {
+				console.error("Failed to save setting", error);
+			}
+			await findKeybindConflicts();
 		}
 	}
 
 	useEffect(() => {
 		setSettings(noiseSettings);
+		findKeybindConflicts();
 	}, [noiseSettings]);
 
+	useEffect(() => {
+		invoke("list_playing").then((playing) => setPlaying(playing as Playback[]));
+
+		const unlistenStarted = listen<Playback>("playback-started", (event) => {
+			setPlaying((playing) => [...playing, event.payload]);
+		});
+		const unlistenFinished = listen<Playback>("playback-finished", (event) => {
+			setPlaying((playing) =>
+				playing.filter((playback) => playback.id !== event.payload.id)
+			);
+		});
+
+		return () => {
+			unlistenStarted.then((unlisten) => unlisten());
+			unlistenFinished.then((unlisten) => unlisten());
+		};
+	}, []);
+
 	useEffect(() => {
 		async function fetchDurations() {
 			const durations = await Promise.all(
@@ -80,13 +121,21 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 						<th className="px-2">Listener</th>
 						<th className="px-2"></th>
 						<th className=""></th>
+						<th className=""></th>
 					</tr>
 				</thead>
 				<tbody>
 					{settings?.map((setting, idx) => {
+						const isPlaying = playing.some(
+							(playback) => playback.filename === setting.filename
+						);
+						const hasConflict = conflicts.some((conflict) =>
+							conflict.filenames.includes(setting.filename)
+						);
+
 						return (
 							<tr key={idx}>
-								<td className="text-left">
+								<td className={`text-left ${isPlaying ? "text-green-400" : ""}`}>
 									{setting.filename.length > 20
 										? setting.filename.slice(0, 20) + "..."
 										: setting.filename}
@@ -98,23 +147,22 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 											className="flex align-center justify-end"
 											htmlFor="newSetting"
 										>
-											Alt +
+											{leaderKey ?? "Alt"} +
 										</label>
 										<input
-											className="bg-neutral-900 shadow-none border border-transparent hover:border-white transition-all duration-150 w-6 p-0 align-center text-center"
+											className={`bg-neutral-900 shadow-none border ${
+												hasConflict ? "border-red-500" : "border-transparent"
+											} hover:border-white transition-all duration-150 w-28 p-0 align-center text-center`}
 											type="text"
 											id={`newKeybind-${idx}`}
-											maxLength={1}
 											placeholder={setting.keybind?.toUpperCase() || "?"}
 											style={{ textTransform: "uppercase" }}
 											onChange={(e) => {
 												const nextSettings = settings?.map((nextSetting) => {
 													if (nextSetting.filename === setting.filename) {
 														return {
-															filename: nextSetting.filename,
+															...nextSetting,
 															keybind: e.target.value.toUpperCase(),
-															userVolume: nextSetting.userVolume,
-															listenerVolume: nextSetting.listenerVolume,
 														};
 													} else {
 														return nextSetting;
@@ -133,16 +181,14 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 											id={`newUserVolume-${idx}`}
 											min={0}
 											max={200}
-											placeholder={setting?.userVolume.toString() || "1"}
+											placeholder={setting?.userVolume.toString() || "100"}
 											onChange={(e) => {
 												const userVolume = parseInt(e.target.value);
 												const nextSettings = settings?.map((nextSetting) => {
 													if (nextSetting.filename === setting.filename) {
 														return {
-															filename: nextSetting.filename,
-															keybind: nextSetting.keybind,
+															...nextSetting,
 															userVolume: userVolume,
-															listenerVolume: nextSetting.listenerVolume,
 														};
 													} else {
 														return nextSetting;
@@ -167,15 +213,13 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 											id={`newListenerVolume-${idx}`}
 											min={0}
 											max={200}
-											placeholder={setting?.listenerVolume.toString() || "1"}
+											placeholder={setting?.listenerVolume.toString() || "100"}
 											onChange={(e) => {
 												const listenerVolume = parseInt(e.target.value, 10);
 												const nextSettings = settings?.map((nextSetting) => {
 													if (nextSetting.filename === setting.filename) {
 														return {
-															filename: nextSetting.filename,
-															keybind: nextSetting.keybind,
-															userVolume: nextSetting.userVolume,
+															...nextSetting,
 															listenerVolume: listenerVolume,
 														};
 													} else {
@@ -209,6 +253,18 @@ const NoiseTable = ({ noiseSettings }: NoiseTable) => {
 										Preview
 									</button>
 								</td>
+								<td>
+									{isPlaying ? (
+										<button
+											className="px-3 py-1 text-sm bg-black border border-neutral-800 hover:border-white transition-all duration-150 hover:shadow-neutral-500 hover:shadow-sm"
+											onClick={() => stopSound(setting.filename)}
+										>
+											Stop
+										</button>
+									) : (
+										<></>
+									)}
+								</td>
 							</tr>
 						);
 					})}
@@ -9,13 +9,192 @@ export interface File {
 
 export interface Setting {
 	filename: string;
-	keybind: string;
+	keybind: string | null;
+	/** Percent, 0 to 200, 100 plays the sound as it is */
 	userVolume: number;
 	listenerVolume: number;
+	tags: string[];
+	effects: EffectConfig[];
+	startMs: number | null;
+	endMs: number | null;
+	fadeInMs: number;
+	fadeOutMs: number;
+	playMode: PlayMode;
+	chokeGroup: string | null;
+	maxPolyphony: number | null;
+}
+
+export type PlayMode = "overlap" | "restart" | "toggle" | "hold" | "loop";
+
+export interface TrimPoints {
+	startMs: number | null;
+	endMs: number | null;
+}
+
+export type EffectConfig =
+	| ({ kind: "gain" } & GainSettings)
+	| ({ kind: "echo" } & EchoSettings)
+	| ({ kind: "distortion" } & DistortionSettings)
+	| ({ kind: "pitch" } & PitchSettings)
+	| ({ kind: "eq" } & EqSettings)
+	| ({ kind: "filter" } & FilterSettings)
+	| ({ kind: "reverb" } & ReverbSettings);
+
+export interface GainSettings {
+	db: number;
+}
+
+export interface PitchSettings {
+	semitones: number;
+	speed: number;
+}
+
+export type EqBand =
+	| { type: "lowShelf"; frequency: number; gainDb: number }
+	| { type: "peaking"; frequency: number; gainDb: number; q: number }
+	| { type: "highShelf"; frequency: number; gainDb: number };
+
+export interface EqSettings {
+	bands: EqBand[];
+}
+
+export interface FilterSettings {
+	highPass: number | null;
+	lowPass: number | null;
+}
+
+export interface ReverbSettings {
+	preset: "room" | "hall" | "bathroom" | "stadium";
+	wet: number;
+	dry: number;
+	decay: number;
+}
+
+export interface EchoSettings {
+	delayMs: number;
+	feedback: number;
+	wet: number;
+}
+
+export interface DistortionSettings {
+	overdrive: number | null;
+	hardClip: number | null;
+	bitcrush: BitcrushSettings | null;
+}
+
+export interface BitcrushSettings {
+	bitDepth: number;
+	downsample: number;
+}
+
+export interface Profile {
+	name: string;
+	noiseSettings: Setting[];
+	inputDevice?: string;
+	outputDevice?: string;
+	activeTag: string | null;
 }
 
 export interface SettingsFile {
 	inputDevice: string;
 	outputDevice: string;
-	noiseSettings: Setting[];
+	profiles: Profile[];
+	activeProfile: string;
+	stopAllKeybind: string | null;
+	nextProfileKeybind: string | null;
+	volumeUpKeybind: string | null;
+	volumeDownKeybind: string | null;
+	muteKeybind: string | null;
+	queuePlayStopKeybind: string | null;
+	queueNextKeybind: string | null;
+	queuePreviousKeybind: string | null;
+	leaderKey: string;
+	voiceChatEq: boolean;
+	targetLufs: number | null;
+	masterBus: MasterBusSettings;
+	userMasterVolume: MasterVolume;
+	listenerMasterVolume: MasterVolume;
+	stopFadeMs: number;
+	soundGroups: SoundGroup[];
+	macros: SoundMacro[];
+	queue: QueueSettings;
+	maxPolyphony: number | null;
+	settingsVersion: number;
+}
+
+export type OutputBus = "user" | "listener";
+
+export interface MasterVolume {
+	/** Percent, 100 leaves sounds unchanged */
+	volume: number;
+	muted: boolean;
+}
+
+export interface MasterBusSettings {
+	ceilingDb: number;
+	compressor: CompressorSettings | null;
+}
+
+export interface CompressorSettings {
+	thresholdDb: number;
+	ratio: number;
+	attackMs: number;
+	releaseMs: number;
+}
+
+export interface LoudnessAnalysis {
+	integratedLufs: number | null;
+	truePeakDb: number;
+}
+
+export interface Playback {
+	id: number;
+	filename: string;
+}
+
+export interface KeybindConflict {
+	keybind: string;
+	filenames: string[];
+}
+
+export type PickMode = "random" | "roundRobin";
+
+export interface GroupMember {
+	filename: string;
+	weight: number;
+}
+
+export interface SoundGroup {
+	name: string;
+	keybind: string | null;
+	members: GroupMember[];
+	pickMode: PickMode;
+}
+
+export interface MacroStep {
+	filename: string;
+	/** Gap after the previous step, negative overlaps them */
+	offsetMs: number;
+	volume: number;
+}
+
+export interface SoundMacro {
+	name: string;
+	keybind: string | null;
+	steps: MacroStep[];
+}
+
+export type RepeatMode = "off" | "all" | "one";
+
+export interface QueueSettings {
+	shuffle: boolean;
+	repeat: RepeatMode;
+	crossfadeMs: number;
+}
+
+export interface QueueStatus {
+	/** In the order they play */
+	tracks: string[];
+	position: number | null;
+	playing: boolean;
 }